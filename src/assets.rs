@@ -0,0 +1,122 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+// Files shipped inside the binary so the game runs from any directory
+static EMBEDDED: &[( &str, &[u8] )] = &[
+    ( "walls2.png", include_bytes!( "../walls2.png" ) ),
+    ( "sounds/click.wav", include_bytes!( "../sounds/click.wav" ) ),
+    ( "sounds/gem.wav", include_bytes!( "../sounds/gem.wav" ) ),
+    ( "sounds/gem2.wav", include_bytes!( "../sounds/gem2.wav" ) ),
+    ( "sounds/transport.wav", include_bytes!( "../sounds/transport.wav" ) ),
+    ( "levels/level0.mp", include_bytes!( "../levels/level0.mp" ) ),
+    ( "levels/level1.mp", include_bytes!( "../levels/level1.mp" ) ),
+    ( "levels/level2.mp", include_bytes!( "../levels/level2.mp" ) ),
+    ( "levels/level3.mp", include_bytes!( "../levels/level3.mp" ) ),
+    ( "levels/level4.mp", include_bytes!( "../levels/level4.mp" ) ),
+    ( "levels/level5.mp", include_bytes!( "../levels/level5.mp" ) ),
+    ( "levels/level6.mp", include_bytes!( "../levels/level6.mp" ) ),
+    ( "levels/level7.mp", include_bytes!( "../levels/level7.mp" ) ),
+    ( "levels/level8.mp", include_bytes!( "../levels/level8.mp" ) ),
+    ( "levels/level9.mp", include_bytes!( "../levels/level9.mp" ) ),
+    ( "levels/level10.mp", include_bytes!( "../levels/level10.mp" ) ),
+    ( "levels/level11.mp", include_bytes!( "../levels/level11.mp" ) ),
+    ( "levels/level12.mp", include_bytes!( "../levels/level12.mp" ) ),
+    ( "levels/level13.mp", include_bytes!( "../levels/level13.mp" ) ),
+    ( "levels/level14.mp", include_bytes!( "../levels/level14.mp" ) ),
+    ( "levels/level15.mp", include_bytes!( "../levels/level15.mp" ) ),
+    ( "levels/level16.mp", include_bytes!( "../levels/level16.mp" ) ),
+    ( "levels/level17.mp", include_bytes!( "../levels/level17.mp" ) ),
+    ( "levels/level18.mp", include_bytes!( "../levels/level18.mp" ) ),
+    ( "levels/level19.mp", include_bytes!( "../levels/level19.mp" ) ),
+    ( "levels/level20.mp", include_bytes!( "../levels/level20.mp" ) ),
+    ( "levels/level21.mp", include_bytes!( "../levels/level21.mp" ) ),
+    ( "levels/level22.mp", include_bytes!( "../levels/level22.mp" ) ),
+    ( "levels/level23.mp", include_bytes!( "../levels/level23.mp" ) ),
+    ( "levels/level24.mp", include_bytes!( "../levels/level24.mp" ) ),
+    ( "levels/level25.mp", include_bytes!( "../levels/level25.mp" ) ),
+    ( "levels/level26.mp", include_bytes!( "../levels/level26.mp" ) ),
+    ( "levels/level27.mp", include_bytes!( "../levels/level27.mp" ) ),
+    ( "levels/level28.mp", include_bytes!( "../levels/level28.mp" ) ),
+    ( "levels/level29.mp", include_bytes!( "../levels/level29.mp" ) ),
+    ( "levels/level30.mp", include_bytes!( "../levels/level30.mp" ) ),
+    ( "levels/level31.mp", include_bytes!( "../levels/level31.mp" ) ),
+    ( "levels/level32.mp", include_bytes!( "../levels/level32.mp" ) ),
+    ( "levels/level33.mp", include_bytes!( "../levels/level33.mp" ) ),
+    ( "levels/level34.mp", include_bytes!( "../levels/level34.mp" ) ),
+    ( "levels/level35.mp", include_bytes!( "../levels/level35.mp" ) ),
+    ( "levels/level36.mp", include_bytes!( "../levels/level36.mp" ) ),
+    ( "levels/level37.mp", include_bytes!( "../levels/level37.mp" ) ),
+    ( "levels/level38.mp", include_bytes!( "../levels/level38.mp" ) ),
+    ( "levels/level39.mp", include_bytes!( "../levels/level39.mp" ) ),
+    ( "levels/level40.mp", include_bytes!( "../levels/level40.mp" ) ),
+    ( "levels/level41.mp", include_bytes!( "../levels/level41.mp" ) ),
+    ( "levels/level42.mp", include_bytes!( "../levels/level42.mp" ) ),
+    ( "levels/level43.mp", include_bytes!( "../levels/level43.mp" ) ),
+    ( "levels/level44.mp", include_bytes!( "../levels/level44.mp" ) ),
+    ( "levels/level45.mp", include_bytes!( "../levels/level45.mp" ) ),
+    ( "levels/level46.mp", include_bytes!( "../levels/level46.mp" ) ),
+    ( "levels/level47.mp", include_bytes!( "../levels/level47.mp" ) ),
+    ( "levels/level48.mp", include_bytes!( "../levels/level48.mp" ) ),
+    ( "levels/level49.mp", include_bytes!( "../levels/level49.mp" ) ),
+    ( "levels/level50.mp", include_bytes!( "../levels/level50.mp" ) ),
+    ( "levels/level51.mp", include_bytes!( "../levels/level51.mp" ) ),
+    ( "levels/level52.mp", include_bytes!( "../levels/level52.mp" ) ),
+    ( "levels/level53.mp", include_bytes!( "../levels/level53.mp" ) ),
+];
+
+pub const OVERRIDE_ENV_VAR: &str = "BEAM_PUZZLE_ASSETS";
+pub const OVERRIDE_ARG: &str = "--assets";
+
+// Resolves asset names like "sounds/gem.wav". A file in the override directory replaces the embedded copy
+pub struct Assets{
+    override_dir: Option<PathBuf>
+}
+
+impl Assets{
+    pub fn new( override_dir: Option<PathBuf> ) -> Assets {
+        return Assets{ override_dir };
+    }
+
+    // The override directory is given with --assets <dir> or the BEAM_PUZZLE_ASSETS environment variable
+    pub fn from_args( args: &[String] ) -> Assets {
+        let arg_dir = args.iter().position( |arg| arg == OVERRIDE_ARG ).and_then( |idx| args.get( idx+1 ) ).map( PathBuf::from );
+        let override_dir = arg_dir.or_else( || env::var_os( OVERRIDE_ENV_VAR ).map( PathBuf::from ) );
+        return Assets::new( override_dir );
+    }
+
+    pub fn read( &self, name: &str ) -> Option<Vec<u8>> {
+        if let Some( dir ) = &self.override_dir {
+            if let Ok( data ) = fs::read( dir.join( name ) ) {
+                return Some( data );
+            }
+        }
+        return EMBEDDED.iter().find( |( embedded_name, _ )| *embedded_name == name ).map( |( _, data )| data.to_vec() );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+    use super::EMBEDDED;
+
+    // Every level and sound on disk must be in the list, and nothing in the list may be missing from disk
+    #[test]
+    fn embedded_files_match_the_asset_directories() {
+        let root = Path::new( env!( "CARGO_MANIFEST_DIR" ) );
+        let mut on_disk: Vec<String> = vec![ "walls2.png".to_string() ];
+        for ( dir, extension ) in [ ( "levels", "mp" ), ( "sounds", "wav" ) ].iter() {
+            for entry in fs::read_dir( root.join( dir ) ).unwrap() {
+                let path = entry.unwrap().path();
+                if path.extension().map_or( false, |ext| ext == *extension ) {
+                    on_disk.push( format!( "{}/{}", dir, path.file_name().unwrap().to_str().unwrap() ) );
+                }
+            }
+        }
+        let mut embedded: Vec<String> = EMBEDDED.iter().map( |( name, _ )| name.to_string() ).collect();
+        on_disk.sort();
+        embedded.sort();
+        assert_eq!( embedded, on_disk );
+    }
+}
@@ -1,12 +1,14 @@
-use std::{collections::HashMap, fs::File, io::Cursor, path::PathBuf};
-use std::io::Read;
-use rodio::{self, OutputStream, OutputStreamHandle};
+use std::{collections::HashMap, io::Cursor};
+use rodio::{self, OutputStream, OutputStreamHandle, Source};
+use super::assets::Assets;
 #[derive(Eq, PartialEq,Hash)]
 pub enum SoundEffect{
     Ping,
     Gem,
     GemSolved,
-    Transport
+    Transport,
+    OrderKept,
+    OrderBroken
 }
 pub struct Audio{
     sounds: HashMap<SoundEffect,Vec<u8>>,
@@ -14,22 +16,26 @@ pub struct Audio{
     stream_handle: OutputStreamHandle
 }
 
-fn load_wav( path: PathBuf ) -> Vec<u8> {
-    println!( "Loading file {:?}", path.to_str());
-    let mut file = File::open( path ).unwrap();
-    let mut data = Vec::new();
-    file.read_to_end( &mut data).unwrap();
-    return data;
+fn load_wav( sounds: &mut HashMap<SoundEffect,Vec<u8>>, effect: SoundEffect, assets: &Assets, name: &str ) {
+    println!( "Loading sound {}", name);
+    match assets.read( name ) {
+        Some( data ) => { sounds.insert( effect, data ); },
+        None => println!( "Missing sound {}", name )
+    }
 }
 
 impl Audio{
-    pub fn new(path: &PathBuf) -> Audio {
+    pub fn new(assets: &Assets) -> Audio {
         let (stream, stream_handle) = rodio::OutputStream::try_default().unwrap();
 
         let mut sounds = HashMap::new();
-        sounds.insert( SoundEffect::Ping, load_wav( path.join("sounds/click.wav") ) );
-        sounds.insert( SoundEffect::Gem, load_wav( path.join("sounds/gem2.wav") ) );
-        sounds.insert( SoundEffect::GemSolved, load_wav( path.join("sounds/gem.wav") ) );
+        load_wav( &mut sounds, SoundEffect::Ping, assets, "sounds/click.wav" );
+        load_wav( &mut sounds, SoundEffect::Gem, assets, "sounds/gem2.wav" );
+        load_wav( &mut sounds, SoundEffect::GemSolved, assets, "sounds/gem.wav" );
+        load_wav( &mut sounds, SoundEffect::Transport, assets, "sounds/transport.wav" );
+        // The gem order sounds reuse the gem and click sounds played faster and slower
+        load_wav( &mut sounds, SoundEffect::OrderKept, assets, "sounds/gem2.wav" );
+        load_wav( &mut sounds, SoundEffect::OrderBroken, assets, "sounds/click.wav" );
 
         Audio{ sounds, stream, stream_handle }
     }
@@ -38,7 +44,16 @@ impl Audio{
         if self.sounds.contains_key(&effect) {
             let copied_sound = self.sounds.get(&effect).unwrap().clone();
             let cursor = Cursor::new(copied_sound);
-            self.stream_handle.play_once(cursor).unwrap().detach();
+            let speed = match effect {
+                SoundEffect::OrderKept => 1.5,
+                SoundEffect::OrderBroken => 0.5,
+                _ => 1.0
+            };
+            if speed == 1.0 {
+                self.stream_handle.play_once(cursor).unwrap().detach();
+            } else if let Ok( source ) = rodio::Decoder::new(cursor) {
+                let _ = self.stream_handle.play_raw( source.speed( speed ).convert_samples() );
+            }
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,558 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use super::level::{BeamDirection, EdgeBehaviour, Level, Tile};
+
+// What happened to the beam on a tile
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BeamEvent{
+    Pass,                                   // the beam crossed the tile. Gems are passed through too
+    Turn( BeamDirection ),                  // a mirror sent the beam off in a new direction
+    Teleport( u32, u32 ),                   // the beam continues from the partner teleport
+    Split,                                  // the beam ends and two new beams leave the splitter at right angles
+    End
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BeamStep{
+    pub x: u32,
+    pub y: u32,
+    pub direction: BeamDirection,           // direction the beam was travelling when it entered the tile
+    pub event: BeamEvent
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BeamTurn{
+    pub x: u32,
+    pub y: u32,
+    pub from: BeamDirection,
+    pub to: BeamDirection
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TeleportJump{
+    pub from: (u32,u32),
+    pub to: (u32,u32),
+    pub direction: BeamDirection,           // direction the beam entered the teleport in
+    pub exit: BeamDirection                 // direction the beam leaves the partner teleport in
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BeamEnd{
+    Blocked( u32, u32 ),                    // a wall, a closed pass, the back of a mirror or the closed side of a receiver
+    Received( u32, u32 ),                   // the beam entered a receiver through its open side
+    ReachedSource( u32, u32 ),
+    UnpairedTeleport( u32, u32 ),
+    Split( u32, u32 ),
+    LeftLevel( u32, u32 ),                  // the beam ran off the edge of a level that absorbs beams after the given tile
+    Looping( u32, u32 )                     // the beam came back to a tile it had entered in the same direction and would go round forever
+}
+
+// The route of one beam from its source or splitter. Steps holds every tile the beam entered in order, the other
+// lists pick out the interesting steps
+#[derive(Clone, Debug, PartialEq)]
+pub struct Beam{
+    pub source: (u32,u32),
+    pub parent: Option<(usize,usize)>,      // beam and step index of the split this beam leaves from
+    pub color: BeamColor,
+    pub steps: Vec<BeamStep>,
+    pub turns: Vec<BeamTurn>,
+    pub teleports: Vec<TeleportJump>,
+    pub end: BeamEnd
+}
+
+impl Beam{
+    pub fn is_looping( &self ) -> bool {
+        if let BeamEnd::Looping( _, _ ) = self.end {
+            return true;
+        }
+        return false;
+    }
+}
+
+// Every beam in a level. A gem is lit if any beam of its colour or a white beam passes through it
+#[derive(Clone, Debug, PartialEq)]
+pub struct BeamPath{
+    pub beams: Vec<Beam>,
+    pub gems: Vec<(u32,u32)>,               // each gem lit, in the order the beams first light it. Spent one-shot gems come first
+    pub bombs: Vec<(u32,u32)>,              // each bomb crossed by a beam of any colour
+    pub receivers: Vec<(u32,u32)>,          // each receiver a beam entered through its open side
+    pub open_doors: Vec<u32>                // channels whose doors the beams hold open, sorted
+}
+
+impl BeamPath{
+    pub fn gem_count( &self ) -> u32 {
+        return self.gems.len() as u32;
+    }
+
+    pub fn receiver_count( &self ) -> u32 {
+        return self.receivers.len() as u32;
+    }
+
+    pub fn is_looping( &self ) -> bool {
+        return self.beams.iter().any( |beam| beam.is_looping() );
+    }
+
+    // The steps of all beams, one beam after the other
+    pub fn steps( &self ) -> impl Iterator<Item = &BeamStep> {
+        return self.beams.iter().flat_map( |beam| beam.steps.iter() );
+    }
+}
+
+// White beams come from the original sources and light gems of any colour. Coloured beams only light gems of their own colour
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum BeamColor{
+    White,
+    Red,
+    Green,
+    Yellow,
+    Purple
+}
+
+impl BeamColor{
+    pub fn lights( &self, gem: Tile ) -> bool {
+        match ( self, gem ) {
+            ( BeamColor::White, _ ) => return gem.is_a_gem(),
+            ( BeamColor::Red, Tile::GemRed ) | ( BeamColor::Green, Tile::GemGreen ) | ( BeamColor::Yellow, Tile::GemYellow ) | ( BeamColor::Purple, Tile::GemPurple ) => return true,
+            _ => return false
+        }
+    }
+
+    // Tint used when drawing the beam and its source
+    pub fn rgb( &self ) -> ( f32, f32, f32 ) {
+        match self {
+            BeamColor::White => return ( 1.0, 1.0, 1.0 ),
+            BeamColor::Red => return ( 1.0, 0.3, 0.3 ),
+            BeamColor::Green => return ( 0.3, 1.0, 0.4 ),
+            BeamColor::Yellow => return ( 1.0, 0.9, 0.3 ),
+            BeamColor::Purple => return ( 0.8, 0.4, 1.0 )
+        }
+    }
+}
+
+// The direction and colour of the beam a source sends out
+pub fn source_beam( tile: Tile ) -> Option<( BeamDirection, BeamColor )> {
+    let beam = match tile {
+        Tile::RaySourceUp => ( BeamDirection::Up, BeamColor::White ),
+        Tile::RaySourceRight => ( BeamDirection::Right, BeamColor::White ),
+        Tile::RaySourceDown => ( BeamDirection::Down, BeamColor::White ),
+        Tile::RaySourceLeft => ( BeamDirection::Left, BeamColor::White ),
+        Tile::RaySourceRedUp => ( BeamDirection::Up, BeamColor::Red ),
+        Tile::RaySourceRedRight => ( BeamDirection::Right, BeamColor::Red ),
+        Tile::RaySourceRedDown => ( BeamDirection::Down, BeamColor::Red ),
+        Tile::RaySourceRedLeft => ( BeamDirection::Left, BeamColor::Red ),
+        Tile::RaySourceGreenUp => ( BeamDirection::Up, BeamColor::Green ),
+        Tile::RaySourceGreenRight => ( BeamDirection::Right, BeamColor::Green ),
+        Tile::RaySourceGreenDown => ( BeamDirection::Down, BeamColor::Green ),
+        Tile::RaySourceGreenLeft => ( BeamDirection::Left, BeamColor::Green ),
+        Tile::RaySourceYellowUp => ( BeamDirection::Up, BeamColor::Yellow ),
+        Tile::RaySourceYellowRight => ( BeamDirection::Right, BeamColor::Yellow ),
+        Tile::RaySourceYellowDown => ( BeamDirection::Down, BeamColor::Yellow ),
+        Tile::RaySourceYellowLeft => ( BeamDirection::Left, BeamColor::Yellow ),
+        Tile::RaySourcePurpleUp => ( BeamDirection::Up, BeamColor::Purple ),
+        Tile::RaySourcePurpleRight => ( BeamDirection::Right, BeamColor::Purple ),
+        Tile::RaySourcePurpleDown => ( BeamDirection::Down, BeamColor::Purple ),
+        Tile::RaySourcePurpleLeft => ( BeamDirection::Left, BeamColor::Purple ),
+        _ => return None
+    };
+    return Some( beam );
+}
+
+fn find_sources( level: &Level ) -> Vec<( u32, u32, BeamDirection, BeamColor )> {
+    let mut sources: Vec<( u32, u32, BeamDirection, BeamColor )> = Vec::new();
+    for y in 0..level.height {
+        for x in 0..level.width {
+            if let Some( ( direction, color ) ) = source_beam( level.front_tile( x, y ) ) {
+                sources.push( ( x, y, direction, color ) );
+            }
+        }
+    }
+    return sources;
+}
+
+// The teleports on each channel
+fn find_teleports( level: &Level ) -> HashMap<u32,Vec<(u32,u32)>> {
+    let mut teleport_pairs: HashMap<u32,Vec<(u32,u32)>> = HashMap::new();
+    for y in 0..level.height {
+        for x in 0..level.width {
+            if let Some( channel ) = level.front_tile( x, y ).teleport_channel() {
+                teleport_pairs.entry( channel ).or_insert_with( ||vec![] ).push( ( x, y ) );
+            }
+        }
+    }
+    return teleport_pairs;
+}
+
+pub fn reverse( direction: BeamDirection ) -> BeamDirection {
+    match direction {
+        BeamDirection::Up => return BeamDirection::Down,
+        BeamDirection::Down => return BeamDirection::Up,
+        BeamDirection::Left => return BeamDirection::Right,
+        BeamDirection::Right => return BeamDirection::Left
+    }
+}
+
+fn at_edge( level: &Level, x: u32, y: u32, direction: BeamDirection ) -> bool {
+    match direction {
+        BeamDirection::Up => return y == 0,
+        BeamDirection::Down => return y+1 == level.height,
+        BeamDirection::Left => return x == 0,
+        BeamDirection::Right => return x+1 == level.width
+    }
+}
+
+// The next tile in the given direction and the direction the beam then travels in. What happens at the edges
+// depends on the level. None if the beam leaves the level
+fn move_beam( level: &Level, x: u32, y: u32, direction: BeamDirection ) -> Option<( u32, u32, BeamDirection )> {
+    let mut direction = direction;
+    if at_edge( level, x, y, direction ) {
+        match level.metadata().edges {
+            EdgeBehaviour::Wrap => {},
+            EdgeBehaviour::Absorb => return None,
+            EdgeBehaviour::Reflect => {
+                direction = reverse( direction );
+                // A level one tile across has nowhere to reflect the beam to
+                if at_edge( level, x, y, direction ) {
+                    return None;
+                }
+            }
+        }
+    }
+    match direction {
+        BeamDirection::Up => return Some( ( x, if y == 0 { level.height-1 } else { y-1 }, direction ) ),
+        BeamDirection::Down => return Some( ( x, if y+1 == level.height { 0 } else { y+1 }, direction ) ),
+        BeamDirection::Left => return Some( ( if x == 0 { level.width-1 } else { x-1 }, y, direction ) ),
+        BeamDirection::Right => return Some( ( if x+1 == level.width { 0 } else { x+1 }, y, direction ) )
+    }
+}
+
+// The direction a beam leaves a mirror in. None if the beam hits the back of the mirror
+pub fn mirror_turn( tile: Tile, direction: BeamDirection ) -> Option<BeamDirection> {
+    match ( tile, direction ) {
+        ( Tile::MovableTopLeft, BeamDirection::Up ) | ( Tile::ImmovableTopLeft, BeamDirection::Up ) => return Some( BeamDirection::Right ),
+        ( Tile::MovableTopLeft, BeamDirection::Left ) | ( Tile::ImmovableTopLeft, BeamDirection::Left ) => return Some( BeamDirection::Down ),
+        ( Tile::MovableTopRight, BeamDirection::Right ) | ( Tile::ImmovableTopRight, BeamDirection::Right ) => return Some( BeamDirection::Down ),
+        ( Tile::MovableTopRight, BeamDirection::Up ) | ( Tile::ImmovableTopRight, BeamDirection::Up ) => return Some( BeamDirection::Left ),
+        ( Tile::MovableBottomLeft, BeamDirection::Down ) | ( Tile::ImmovableBottomLeft, BeamDirection::Down ) => return Some( BeamDirection::Right ),
+        ( Tile::MovableBottomLeft, BeamDirection::Left ) | ( Tile::ImmovableBottomLeft, BeamDirection::Left ) => return Some( BeamDirection::Up ),
+        ( Tile::MovableBottomRight, BeamDirection::Down ) | ( Tile::ImmovableBottomRight, BeamDirection::Down ) => return Some( BeamDirection::Left ),
+        ( Tile::MovableBottomRight, BeamDirection::Right ) | ( Tile::ImmovableBottomRight, BeamDirection::Right ) => return Some( BeamDirection::Up ),
+        ( Tile::RotatableTopLeft, _ ) => return mirror_turn( Tile::ImmovableTopLeft, direction ),
+        ( Tile::RotatableTopRight, _ ) => return mirror_turn( Tile::ImmovableTopRight, direction ),
+        ( Tile::RotatableBottomLeft, _ ) => return mirror_turn( Tile::ImmovableBottomLeft, direction ),
+        ( Tile::RotatableBottomRight, _ ) => return mirror_turn( Tile::ImmovableBottomRight, direction ),
+        _ => return None
+    }
+}
+
+fn is_mirror( tile: Tile ) -> bool {
+    return tile == Tile::MovableTopLeft || tile == Tile::MovableTopRight || tile == Tile::MovableBottomLeft || tile == Tile::MovableBottomRight ||
+        tile == Tile::ImmovableTopLeft || tile == Tile::ImmovableTopRight || tile == Tile::ImmovableBottomLeft || tile == Tile::ImmovableBottomRight ||
+        tile.is_rotatable();
+}
+
+fn perpendicular( direction: BeamDirection ) -> [ BeamDirection; 2 ] {
+    match direction {
+        BeamDirection::Up | BeamDirection::Down => return [ BeamDirection::Left, BeamDirection::Right ],
+        BeamDirection::Left | BeamDirection::Right => return [ BeamDirection::Up, BeamDirection::Down ]
+    }
+}
+
+// A beam still to be traced
+struct BeamHead{
+    x: u32,
+    y: u32,
+    direction: BeamDirection,
+    color: BeamColor,
+    parent: Option<(usize,usize)>
+}
+
+// Follow the beam of every source until it stops. Opening a door can send the beams over other switches
+// so the beams are traced again with the doors the last trace opened until the doors stop changing. Doors
+// that would open and close forever are left as they were when the first repeat was found. Only reads the level
+pub fn trace_beam( level: &Level ) -> BeamPath {
+    let mut open_doors: Vec<u32> = Vec::new();
+    let mut tried: HashSet<Vec<u32>> = HashSet::new();
+    loop {
+        let mut path = trace_with_doors( level, &open_doors );
+        let mut switched: Vec<u32> = path.steps().filter_map( |step| level.front_tile( step.x, step.y ).switch_channel() ).collect();
+        switched.sort();
+        switched.dedup();
+        tried.insert( open_doors.clone() );
+        if switched == open_doors || tried.contains( &switched ) {
+            path.open_doors = open_doors;
+            return path;
+        }
+        open_doors = switched;
+    }
+}
+
+// The gems the beams light in the order they reach them. Every source starts at the same time and a split beam starts
+// where its parent reached the splitter, so a gem is reached after as many steps as the beams took to get to it.
+// Beams are traced parents first so each beam's start is known before its children
+pub fn gems_in_order( level: &Level, beams: &Vec<Beam> ) -> Vec<(u32,u32)> {
+    let mut starts: Vec<usize> = Vec::new();
+    let mut hits: Vec<(usize,(u32,u32))> = Vec::new();
+    for beam in beams.iter() {
+        let start = beam.parent.map_or( 0, |( parent, step )| starts[ parent ] + step + 1 );
+        starts.push( start );
+        for ( idx, step ) in beam.steps.iter().enumerate() {
+            if step.event == BeamEvent::Pass && beam.color.lights( level.front_tile( step.x, step.y ) ) {
+                hits.push( ( start+idx, ( step.x, step.y ) ) );
+            }
+        }
+    }
+    // The sort is stable so gems reached after the same number of steps stay in the order the beams were traced
+    hits.sort_by_key( |( steps, _ )| *steps );
+    let mut gems: Vec<(u32,u32)> = Vec::new();
+    for ( _, gem ) in hits {
+        if !gems.contains( &gem ) {
+            gems.push( gem );
+        }
+    }
+    return gems;
+}
+
+// Trace the beams with the doors of the given channels open and every other door closed
+fn trace_with_doors( level: &Level, open_doors: &Vec<u32> ) -> BeamPath {
+    // Spent one-shot gems count as lit before anything the beams reach
+    let mut path = BeamPath{ beams: Vec::new(), gems: level.attempt().spent_gems.clone(), bombs: Vec::new(), receivers: Vec::new(), open_doors: open_doors.clone() };
+    let teleports = find_teleports( level );
+    let mut heads: VecDeque<BeamHead> = find_sources( level ).into_iter()
+        .map( |( x, y, direction, color )| BeamHead{ x, y, direction, color, parent: None } ).collect();
+    // A splitter only splits a beam of each colour coming from each direction once so split beams cant multiply forever
+    let mut splits: HashSet<(u32,u32,BeamDirection,BeamColor)> = HashSet::new();
+    while let Some( head ) = heads.pop_front() {
+        let beam = trace_from( level, &head, &teleports, open_doors );
+        if let BeamEnd::Split( x, y ) = beam.end {
+            let incoming = beam.steps.last().unwrap().direction;
+            if splits.insert( ( x, y, incoming, head.color ) ) {
+                for direction in perpendicular( incoming ).iter() {
+                    heads.push_back( BeamHead{ x, y, direction: *direction, color: head.color, parent: Some( ( path.beams.len(), beam.steps.len()-1 ) ) } );
+                }
+            }
+        }
+        path.beams.push( beam );
+    }
+    path.gems.extend( gems_in_order( level, &path.beams ).into_iter().filter( |gem| !level.is_spent_gem( gem.0, gem.1 ) ) );
+    let mut bombs: Vec<(u32,u32)> = Vec::new();
+    for step in path.steps().filter( |step| step.event == BeamEvent::Pass && level.front_tile( step.x, step.y ) == Tile::Bomb ) {
+        if !bombs.contains( &( step.x, step.y ) ) {
+            bombs.push( ( step.x, step.y ) );
+        }
+    }
+    path.bombs = bombs;
+    for beam in path.beams.iter() {
+        if let BeamEnd::Received( x, y ) = beam.end {
+            if !path.receivers.contains( &( x, y ) ) {
+                path.receivers.push( ( x, y ) );
+            }
+        }
+    }
+    return path;
+}
+
+// Follow one beam until it stops. Where the beam goes next only depends on the tile it enters and its direction, so
+// entering a tile the same way twice means it is going round in a loop. The loop is traced once
+fn trace_from( level: &Level, head: &BeamHead, teleports: &HashMap<u32,Vec<(u32,u32)>>, open_doors: &Vec<u32> ) -> Beam {
+    let mut beam = Beam{ source: ( head.x, head.y ), parent: head.parent, color: head.color, steps: Vec::new(), turns: Vec::new(), teleports: Vec::new(), end: BeamEnd::LeftLevel( head.x, head.y ) };
+    let mut entered_before: HashSet<(u32,u32,BeamDirection)> = HashSet::new();
+    // Move the beam out of its source ( its normally a blocker )
+    let ( mut beam_x, mut beam_y, mut direction ) = match move_beam( level, head.x, head.y, head.direction ) {
+        Some( next ) => next,
+        None => return beam
+    };
+    loop {
+        if !entered_before.insert( ( beam_x, beam_y, direction ) ) {
+            beam.end = BeamEnd::Looping( beam_x, beam_y );
+            return beam;
+        }
+        let tile = level.front_tile( beam_x, beam_y );
+        let entered = direction;
+        let mut event = BeamEvent::Pass;
+        let closed_door = tile.door_channel().map_or( false, |channel| !open_doors.contains( &channel ) );
+        if let Some( entry ) = tile.receiver_entry() {
+            event = BeamEvent::End;
+            beam.end = if direction == entry { BeamEnd::Received( beam_x, beam_y ) } else { BeamEnd::Blocked( beam_x, beam_y ) };
+        } else if tile.is_ray_blocker( direction ) || closed_door || level.is_spent_gem( beam_x, beam_y ) {
+            event = BeamEvent::End;
+            beam.end = BeamEnd::Blocked( beam_x, beam_y );
+        } else if tile.is_ray_source() {
+            event = BeamEvent::End;
+            beam.end = BeamEnd::ReachedSource( beam_x, beam_y );
+        } else if tile.is_a_gem() {
+            // The beam passes over gems. The gems it lights are collected once every beam is traced
+        } else if tile.is_splitter() {
+            event = BeamEvent::Split;
+            beam.end = BeamEnd::Split( beam_x, beam_y );
+        } else if is_mirror( tile ) {
+            match mirror_turn( tile, direction ) {
+                Some( turned ) => {
+                    event = BeamEvent::Turn( turned );
+                    beam.turns.push( BeamTurn{ x: beam_x, y: beam_y, from: direction, to: turned } );
+                    direction = turned;
+                },
+                None => {
+                    event = BeamEvent::End;
+                    beam.end = BeamEnd::Blocked( beam_x, beam_y );
+                }
+            }
+        } else if let Some( pair ) = tile.teleport_channel().and_then( |channel| teleports.get( &channel ) ) {
+            // A teleport without a partner has nowhere to send the beam. Loaded levels always have two on a channel
+            if pair.len() != 2 {
+                event = BeamEvent::End;
+                beam.end = BeamEnd::UnpairedTeleport( beam_x, beam_y );
+            } else {
+                let ( to_x, to_y ) = if ( beam_x, beam_y ) == pair[ 0 ] { pair[ 1 ] } else { pair[ 0 ] };
+                let exit = level.teleport_exit( to_x, to_y ).unwrap_or( direction );
+                event = BeamEvent::Teleport( to_x, to_y );
+                beam.teleports.push( TeleportJump{ from: ( beam_x, beam_y ), to: ( to_x, to_y ), direction, exit } );
+                direction = exit;
+            }
+        }
+        beam.steps.push( BeamStep{ x: beam_x, y: beam_y, direction: entered, event } );
+        match event {
+            BeamEvent::End | BeamEvent::Split => return beam,
+            BeamEvent::Teleport( to_x, to_y ) => {
+                beam_x = to_x;
+                beam_y = to_y;
+            },
+            _ => {}
+        }
+        match move_beam( level, beam_x, beam_y, direction ) {
+            Some( next ) => {
+                beam_x = next.0;
+                beam_y = next.1;
+                direction = next.2;
+            },
+            None => {
+                beam.end = BeamEnd::LeftLevel( beam_x, beam_y );
+                return beam;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::level::{BeamDirection, Level};
+    use super::super::level_text;
+    use super::{trace_beam, BeamEnd, TeleportJump};
+
+    // A level with floor under every tile and the given metadata lines
+    fn level( front: &[&str], metadata: &str ) -> Level {
+        let floor: Vec<String> = front.iter().map( |row| "2".repeat( row.len() ) ).collect();
+        let empty: Vec<String> = front.iter().map( |row| ".".repeat( row.len() ) ).collect();
+        let text = format!( "beam_puzzle level 0\nhas_solution 0\n{}back\n{}\nfront\n{}\nsolution\n{}\n", metadata, floor.join( "\n" ), front.join( "\n" ), empty.join( "\n" ) );
+        return level_text::level_from_text( &text ).unwrap();
+    }
+
+    fn gems( front: &[&str], metadata: &str ) -> Vec<(u32,u32)> {
+        return trace_beam( &level( front, metadata ) ).gems;
+    }
+
+    // The top source is traced first but the bottom beam reaches its gem in fewer steps
+    #[test]
+    fn gems_are_listed_in_the_order_they_are_reached() {
+        let text = "beam_puzzle level 0\nhas_solution 0\nedges absorb\nback\n22222222\n22222222\n22222222\nfront\n>......R\n........\n>.G.....\nsolution\n........\n........\n........\n";
+        let level = level_text::level_from_text( text ).unwrap();
+        assert_eq!( trace_beam( &level ).gems, vec![ ( 2, 2 ), ( 7, 0 ) ] );
+    }
+
+    // The red and green beams both reach the top splitter from below. The green one must still be split to reach its gem
+    #[test]
+    fn splitters_split_each_colour() {
+        let text = "beam_puzzle level 0\nhas_solution 0\nedges absorb\nback\n22222\n22222\nfront\nG.Z..\nf.Z.l\nsolution\n.....\n.....\n";
+        let level = level_text::level_from_text( text ).unwrap();
+        assert_eq!( trace_beam( &level ).gems, vec![ ( 0, 0 ) ] );
+    }
+
+    #[test]
+    fn sources_send_their_colour_their_way() {
+        assert_eq!( gems( &[ "G.<" ], "edges absorb\n" ), vec![ ( 0, 0 ) ] );
+        assert_eq!( gems( &[ "G.>", "..^" ], "edges absorb\n" ), vec![] );
+        assert_eq!( gems( &[ "G..", "^.." ], "edges absorb\n" ), vec![ ( 0, 0 ) ] );
+        assert_eq!( gems( &[ "f.G" ], "edges absorb\n" ), vec![] );
+        assert_eq!( gems( &[ "j.G" ], "edges absorb\n" ), vec![ ( 2, 0 ) ] );
+    }
+
+    // The top right mirror turns a beam going right downwards and stops a beam going left
+    #[test]
+    fn mirrors_turn_beams_and_block_them_from_behind() {
+        let path = trace_beam( &level( &[ ">..b", "...G" ], "edges absorb\n" ) );
+        assert_eq!( path.gems, vec![ ( 3, 1 ) ] );
+        assert_eq!( path.beams[ 0 ].turns.len(), 1 );
+        assert_eq!( trace_beam( &level( &[ "Gb.<" ], "edges absorb\n" ) ).beams[ 0 ].end, BeamEnd::Blocked( 1, 0 ) );
+    }
+
+    #[test]
+    fn splitters_send_beams_both_ways() {
+        let path = trace_beam( &level( &[ "..G.", ">.Z.", "..G." ], "edges absorb\n" ) );
+        assert_eq!( path.beams.len(), 3 );
+        assert_eq!( path.beams[ 0 ].end, BeamEnd::Split( 2, 1 ) );
+        assert_eq!( path.beams[ 1 ].parent, Some( ( 0, 1 ) ) );
+        assert_eq!( path.gems.len(), 2 );
+    }
+
+    #[test]
+    fn edges_wrap_absorb_or_reflect_beams() {
+        assert_eq!( gems( &[ "<.G" ], "" ), vec![ ( 2, 0 ) ] );
+        assert_eq!( gems( &[ "<.G" ], "edges absorb\n" ), vec![] );
+        assert_eq!( gems( &[ "<.G" ], "edges reflect\n" ), vec![ ( 2, 0 ) ] );
+        assert_eq!( trace_beam( &level( &[ ">.." ], "edges absorb\n" ) ).beams[ 0 ].end, BeamEnd::LeftLevel( 2, 0 ) );
+        assert_eq!( trace_beam( &level( &[ ">.." ], "" ) ).beams[ 0 ].end, BeamEnd::ReachedSource( 0, 0 ) );
+    }
+
+    #[test]
+    fn teleports_send_beams_to_their_partner() {
+        let path = trace_beam( &level( &[ ">%..", "..%G" ], "edges absorb\n" ) );
+        assert_eq!( path.gems, vec![ ( 3, 1 ) ] );
+        assert_eq!( path.beams[ 0 ].teleports, vec![ TeleportJump{ from: ( 1, 0 ), to: ( 2, 1 ), direction: BeamDirection::Right, exit: BeamDirection::Right } ] );
+    }
+
+    // The lower teleport sends beams right so the beam keeps jumping between the teleports and wrapping round the level
+    #[test]
+    fn beams_that_come_back_the_same_way_are_looping() {
+        let path = trace_beam( &level( &[ "v..", "%..", ".%." ], "exit 1 2 right\n" ) );
+        assert!( path.is_looping() );
+        assert_eq!( path.beams[ 0 ].end, BeamEnd::Looping( 2, 2 ) );
+    }
+
+    #[test]
+    fn switches_on_the_beam_open_doors() {
+        let path = trace_beam( &level( &[ ">S.NG" ], "edges absorb\n" ) );
+        assert_eq!( path.open_doors, vec![ 1 ] );
+        assert_eq!( path.gems, vec![ ( 4, 0 ) ] );
+        let path = trace_beam( &level( &[ ">.NG", "S..." ], "edges absorb\n" ) );
+        assert!( path.open_doors.is_empty() );
+        assert_eq!( path.beams[ 0 ].end, BeamEnd::Blocked( 2, 0 ) );
+    }
+
+    #[test]
+    fn bombs_are_lit_by_passing_beams() {
+        let path = trace_beam( &level( &[ ">.O.G" ], "edges absorb\n" ) );
+        assert_eq!( path.bombs, vec![ ( 2, 0 ) ] );
+        assert_eq!( path.gems, vec![ ( 4, 0 ) ] );
+    }
+
+    // Receivers take beams through their open side only
+    #[test]
+    fn receivers_take_beams_through_their_open_side() {
+        let path = trace_beam( &level( &[ ">..X" ], "edges absorb\n" ) );
+        assert_eq!( path.receivers, vec![ ( 3, 0 ) ] );
+        assert_eq!( path.beams[ 0 ].end, BeamEnd::Received( 3, 0 ) );
+        let path = trace_beam( &level( &[ ">..U" ], "edges absorb\n" ) );
+        assert!( path.receivers.is_empty() );
+        assert_eq!( path.beams[ 0 ].end, BeamEnd::Blocked( 3, 0 ) );
+    }
+
+    // A spent one-shot gem stays lit but blocks the beam so the gem behind it goes out
+    #[test]
+    fn spent_one_shot_gems_block_beams() {
+        let mut level = level( &[ ">y.G" ], "edges absorb\n" );
+        let path = trace_beam( &level );
+        assert_eq!( path.gems, vec![ ( 1, 0 ), ( 3, 0 ) ] );
+        assert_eq!( level.spend_gems( &path.gems ), vec![ ( 1, 0 ) ] );
+        let path = trace_beam( &level );
+        assert_eq!( path.gems, vec![ ( 1, 0 ) ] );
+        assert_eq!( path.beams[ 0 ].end, BeamEnd::Blocked( 1, 0 ) );
+    }
+}
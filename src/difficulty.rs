@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use super::level::{Level, SolutionStatus, Tile};
+use super::beam;
+use super::solver::{self, Move, PieceState, SolveResult, Solver};
+
+// Measured features of a level and the difficulty score built from them
+pub struct Difficulty{
+    pub movable_pieces: usize,
+    pub search_space: f64,                  // ways the movable pieces can be laid out on the open tiles
+    pub min_moves: Option<usize>,           // None if the solver gave up. Levels too big to search get an upper bound
+    pub teleports_used: usize,              // teleport jumps the solved beams make
+    pub dead_ends: Option<usize>,           // reachable layouts that can no longer be solved, see Solver::dead_ends
+    pub score: f32
+}
+
+// Number of distinct layouts of the pieces over the tiles they can be dragged to. Pieces of the same kind are interchangeable.
+// Each rotatable mirror multiplies the layouts by its four orientations
+fn count_layouts( level: &Level ) -> f64 {
+    let mut open_tiles = 0;
+    let mut rotatables = 0;
+    let mut kinds: HashMap<Tile,u32> = HashMap::new();
+    for y in 0..level.height {
+        for x in 0..level.width {
+            let tile = level.front_tile( x, y );
+            if tile.is_movable() {
+                *kinds.entry( tile ).or_insert( 0 ) += 1;
+            }
+            if tile.is_rotatable() {
+                rotatables += 1;
+            }
+            if level.is_open_tile( x, y ) || tile.is_movable() {
+                open_tiles += 1;
+            }
+        }
+    }
+    let mut layouts = 4.0f64.powi( rotatables );
+    let mut placed = 0;
+    for count in kinds.values() {
+        for idx in 0..*count {
+            layouts *= ( open_tiles - placed ) as f64 / ( idx+1 ) as f64;
+            placed += 1;
+        }
+    }
+    return layouts;
+}
+
+// Play the drags on a copy of the level and count the teleport jumps the beams then make
+fn teleports_on_solved_beam( level: &Level, moves: &Vec<Move> ) -> usize {
+    let mut solved = level.clone();
+    for piece_move in moves.iter() {
+        solved.set_front_tile( piece_move.from.0, piece_move.from.1, Tile::EmptyPiece );
+        solved.set_front_tile( piece_move.to.0, piece_move.to.1, piece_move.tile );
+    }
+    return beam::trace_beam( &solved ).beams.iter().map( |beam| beam.teleports.len() ).sum();
+}
+
+pub fn analyze_level( level: &Level, max_states: usize ) -> Difficulty {
+    let mut solver = Solver::new( level );
+    let start = Solver::initial_state( level );
+    let ( min_moves, teleports_used, solvable ) = match solver.solve( &start, max_states ) {
+        SolveResult::Solved( moves ) | SolveResult::Found( moves ) => ( Some( moves.len() ), teleports_on_solved_beam( level, &moves ), Some( true ) ),
+        SolveResult::Unsolvable => ( None, 0, Some( false ) ),
+        SolveResult::GaveUp( _ ) => ( None, 0, None )
+    };
+    let dead_ends = solver.dead_ends( &start, solvable, max_states );
+    let search_space = count_layouts( level );
+
+    // Unknown values count as the worst case. The solution length weighs the most as that is what players notice
+    let dead_end_share = dead_ends.as_ref().map_or( 1.0, |dead_ends| dead_ends.dead_ends as f32 / dead_ends.explored.max( 1 ) as f32 );
+    let score = min_moves.unwrap_or( 20 ) as f32 * 2.0 + start.len() as f32 + teleports_used as f32 * 1.5 + ( search_space.max( 1.0 ).log10() as f32 ) + dead_end_share * 3.0;
+    return Difficulty{ movable_pieces: start.len(), search_space, min_moves, teleports_used, dead_ends: dead_ends.map( |dead_ends| dead_ends.dead_ends ), score };
+}
+
+// The distinct ways of solving a level compared with the solution stored in the level. Layouts that differ only
+// in pieces the ray never touches count as one way, so the ways are listed as the smallest sets of pieces on the
+// ray that win rather than as whole layouts
+pub struct SolutionCheck{
+    pub piece_sets: Vec<PieceState>,        // the smallest sets of pieces on the ray that win, see Solver::solved_placements
+    pub complete: bool,                     // false if there were more sets than the limit or too many to enumerate
+    pub stored: Option<PieceState>,         // pieces the ray passes over in the stored solution. None if it is missing or broken
+    pub intended_moves: Option<usize>,      // the level's par or else the drags needed to reach the stored solution
+    pub shortest_moves: Option<usize>
+}
+
+impl SolutionCheck{
+    pub fn is_unique( &self ) -> bool {
+        return self.complete && self.piece_sets.len() == 1 && self.stored.as_ref() == Some( &self.piece_sets[ 0 ] );
+    }
+
+    // True if the level can be solved in fewer drags than the designer meant
+    pub fn has_shortcut( &self ) -> bool {
+        match ( self.shortest_moves, self.intended_moves ) {
+            ( Some( shortest ), Some( intended ) ) => return shortest < intended,
+            _ => return false
+        }
+    }
+}
+
+pub fn check_solutions( level: &Level, max_sets: usize, max_states: usize ) -> SolutionCheck {
+    let mut solver = Solver::new( level );
+    let start = Solver::initial_state( level );
+    let ( mut piece_sets, complete ) = solver.solved_placements( solver::MAX_PLACEMENT_NODES, max_sets );
+    piece_sets.sort_by_key( |set| set.iter().map( |( offset, tile )| ( *offset, *tile as u8 ) ).collect::<Vec<(usize,u8)>>() );
+
+    let mut solved = level.clone();
+    let stored = match level.verify_solution() {
+        SolutionStatus::Solved => {
+            solved.apply_solution().unwrap();
+            Some( solver.beam_pieces( &Solver::initial_state( &solved ) ) )
+        },
+        _ => None
+    };
+    let intended_moves = match ( level.metadata().par_moves, stored.as_ref() ) {
+        ( Some( par_moves ), _ ) => Some( par_moves as usize ),
+        ( None, Some( stored ) ) => match solver.solve_towards( &start, stored, max_states ) {
+            SolveResult::Solved( moves ) => Some( moves.len() ),
+            _ => None
+        },
+        _ => None
+    };
+    let shortest_moves = match solver.solve( &start, max_states ) {
+        SolveResult::Solved( moves ) | SolveResult::Found( moves ) => Some( moves.len() ),
+        _ => None
+    };
+    return SolutionCheck{ piece_sets, complete, stored, intended_moves, shortest_moves };
+}
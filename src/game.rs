@@ -1,11 +1,13 @@
 
 use super::{Vertex, level::Tile, page_manager::PageName};
-use super::glutin::event::VirtualKeyCode;
+use super::glutin::event::{MouseButton, VirtualKeyCode};
 
 use super::glium::Surface;
 use super::{Vec2,Vec4};
 use super::Level;
-use super::level::TileEffect;
+use super::level::{AttemptState, GemOrder, TileEffect, LevelError, WinCondition};
+use super::level_pack::{self, LevelPack};
+use super::beam;
 use super::tile_batcher::*;
 use super::config;
 use super::GlyphBrush;
@@ -14,41 +16,142 @@ use super::ui::{button,static_text, MouseState};
 use super::page_manager;
 use super::render_level;
 use super::audio;
+use super::assets::Assets;
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 struct TileMove{
     tile: Tile,
+    from: (u32,u32),
     map_x: u32,
     map_y: u32,
+    drop_at: (u32,u32),                 // the last tile passed that the piece can be left on
+    open_doors: Vec<u32>,               // door channels open when the piece was picked up
     grab_cursor_pos: Vec2,
     last_cursor_pos:Vec2
 }
 
+// A drag or turn the player can take back. The one-shot gems spent before it are restored with it
+struct UndoStep{
+    from: (u32,u32),
+    to: (u32,u32),
+    tile: Tile,                         // the piece as it was before the move
+    attempt: AttemptState
+}
+
 #[derive(Debug, PartialEq)]
 enum GameState{
     ShowingNewLevel( f64 ),
     Playing,
     ShowingSolution( f64 ),
+    LevelFailed( f64 ),
     InGameMenu,
+    PackComplete,
     ChangingPage( PageAction, f64 )
 }
 
 pub struct GamePage{
     level_no: u32,
     game_state: GameState,
+    pack: Option<LevelPack>,
 
     level: Level,
+    load_error: Option<LevelError>,
     tile_move: Option<TileMove>,
+    history: Vec<UndoStep>,
     
     last_jewel_ray_count: u32,           // how many tiles crossed by the ray on the last frame
+    last_open_doors: Vec<u32>,           // door channels open on the last frame
+    last_gem_order: GemOrder,            // how the lit gems followed the gem order on the last frame
     last_map_pos: Option<(u32,u32)>
 }
 
 impl GamePage{
-    pub fn new( config: &config::Config ) -> GamePage {
+    pub fn new( config: &config::Config, assets: &Assets ) -> GamePage {
         let level_no = config.max_level();
-        let level = Level::load_level(level_no);
-        return GamePage{ level_no, game_state: GameState::ShowingNewLevel( 0.0), level, tile_move: None, last_jewel_ray_count: 0, last_map_pos: None };
+        let pack = match level_pack::load_default_pack( assets ) {
+            Ok( pack ) => Some( pack ),
+            Err( err ) => {
+                println!( "Failed to load level pack: {}", err );
+                None
+            }
+        };
+        let mut game_page = GamePage{ level_no, game_state: GameState::ShowingNewLevel( 0.0), pack, level: Level::empty(), load_error: None, tile_move: None, history: Vec::new(),
+            last_jewel_ray_count: 0, last_open_doors: Vec::new(), last_gem_order: GemOrder::Pending, last_map_pos: None };
+        game_page.start_level( 0.0 );
+        return game_page;
+    }
+
+    // Show the intro for the current level or the end of pack screen when all levels have been played
+    fn start_level( &mut self, time_in_page: f64 ) {
+        self.last_jewel_ray_count = 0;
+        self.last_open_doors = Vec::new();
+        self.last_gem_order = GemOrder::Pending;
+        if let Some( pack ) = &self.pack {
+            if self.level_no > pack.len() {
+                self.level = Level::empty();
+                self.load_error = None;
+                self.tile_move = None;
+                self.game_state = GameState::PackComplete;
+                return;
+            }
+        }
+        self.load_level();
+        self.game_state = GameState::ShowingNewLevel( time_in_page );
+        self.level.tile_movable_effect(TileEffect::Hide );
+    }
+
+    // Put the pieces back where the level started them for another attempt
+    fn restart_level( &mut self ) {
+        self.load_level();
+        self.last_jewel_ray_count = 0;
+        self.last_open_doors = Vec::new();
+        self.last_gem_order = GemOrder::Pending;
+        self.game_state = GameState::Playing;
+    }
+
+    // A beam crossed a bomb. The bombs flash and the pieces shake before the level restarts
+    fn fail_level( &mut self, time_in_page: f64 ) {
+        self.game_state = GameState::LevelFailed( time_in_page );
+        for ( x, y ) in self.level.lit_bombs().clone() {
+            self.level.set_effect( x, y, TileEffect::Flash( time_in_page as f32, 1.5 ) );
+        }
+        for y in 0..self.level.height {
+            for x in 0..self.level.width {
+                if self.level.front_tile( x, y ).is_movable() || self.level.front_tile( x, y ).is_rotatable() {
+                    self.level.set_effect( x, y, TileEffect::Punch( time_in_page as f32, Vec2::new( 15.0, 0.0 ) ) );
+                }
+            }
+        }
+    }
+
+    // Load the current level. On failure an empty level is used and the error is shown instead of the level
+    fn load_level( &mut self ) {
+        let result = match &self.pack {
+            Some( pack ) => pack.level( self.level_no ),
+            None => Err( LevelError::BadPack( "no level pack loaded".to_string() ) )
+        };
+        match result {
+            Ok( level ) => {
+                self.level = level;
+                self.load_error = None;
+            },
+            Err( err ) => {
+                println!( "Failed to load level {}: {}", self.level_no, err );
+                self.level = Level::empty();
+                self.load_error = Some( err );
+            }
+        }
+        self.tile_move = None;
+        self.history.clear();
+    }
+
+    // Put back the last piece moved or turned and the one-shot gems as they were before it
+    fn undo( &mut self ) {
+        if let Some( step ) = self.history.pop() {
+            self.level.set_front_tile( step.to.0, step.to.1, Tile::EmptyPiece );
+            self.level.set_front_tile( step.from.0, step.from.1, step.tile );
+            self.level.set_attempt( step.attempt );
+        }
     }
 
     fn ui(&mut self, tile_batcher: &TileBatcher, vertices: &mut Vec<Vertex>, glyph_brush: &mut GlyphBrush, config: &config::Config, mouse_state: &MouseState, page_actions: &mut Vec<PageAction>) {
@@ -66,6 +169,63 @@ impl GamePage{
         &mut || page_actions.push( PageAction::Exit));
     }
 
+    fn pack_complete_ui(&mut self, tile_batcher: &TileBatcher, vertices: &mut Vec<Vertex>, glyph_brush: &mut GlyphBrush, config: &config::Config, mouse_state: &MouseState, page_actions: &mut Vec<PageAction>) {
+        let button_width = config.width() as f32 * 0.8f32;
+        let button_height = 80.0f32;
+        let button_left = ( config.width() as f32 - button_width ) / 2.0;
+        let pack_name = match &self.pack {
+            Some( pack ) => pack.manifest().name.clone(),
+            None => "Level pack".to_string()
+        };
+
+        static_text(tile_batcher, vertices, glyph_brush, config, Vec2::new( button_left, 900.0 ),Vec2::new( button_width, button_height ), &pack_name );
+        static_text(tile_batcher, vertices, glyph_brush, config, Vec2::new( button_left, 800.0 ),Vec2::new( button_width, button_height ), "All levels complete" );
+        button(tile_batcher, vertices, glyph_brush, config, Vec2::new( button_left, 500.0 ),Vec2::new( button_width, button_height ), "Main Menu", &mouse_state, 
+        &mut || page_actions.push( PageAction::Back));
+        button(tile_batcher, vertices, glyph_brush, config, Vec2::new( button_left, 300.0 ),Vec2::new( button_width, button_height ), "Exit Game", &mouse_state, 
+        &mut || page_actions.push( PageAction::Exit));
+    }
+
+    // Fade out the doors that have opened since the last frame and fade back in the ones that have closed.
+    // Open doors stay faintly visible
+    fn animate_doors( &mut self, time_in_page: f64 ) {
+        let open_doors = self.level.open_doors().clone();
+        for y in 0..self.level.height {
+            for x in 0..self.level.width {
+                if let Some( channel ) = self.level.front_tile( x, y ).door_channel() {
+                    let was_open = self.last_open_doors.contains( &channel );
+                    let is_open = open_doors.contains( &channel );
+                    if was_open != is_open {
+                        let ( from_alpha, to_alpha ) = if is_open { ( 1.0, 0.2 ) } else { ( 0.2, 1.0 ) };
+                        self.level.set_effect( x, y, TileEffect::Fade( time_in_page as f32, from_alpha, to_alpha, 0.3 ) );
+                    }
+                }
+            }
+        }
+        self.last_open_doors = open_doors;
+    }
+
+    // What the beams on screen have reached of what the level needs to be won
+    fn progress_text( &self, gems_lit: u32 ) -> String {
+        let gems = format!( "Gems {}/{}", gems_lit, self.level.count_jewels() );
+        let receivers = format!( "Receivers {}/{}", self.level.receivers_hit(), self.level.count_receivers() );
+        match self.level.metadata().win {
+            WinCondition::AllGems => return gems,
+            WinCondition::AllReceivers => return receivers,
+            WinCondition::GemsAndReceivers => return gems + "  " + &receivers,
+            WinCondition::Gems( count ) => return format!( "Gems {}/{}", gems_lit, count.min( self.level.count_jewels() ) )
+        }
+    }
+
+    // The colours the level wants lit in order and whether the beams on screen have kept to it
+    fn gem_order_text( &self ) -> String {
+        let colors: Vec<&str> = self.level.metadata().gem_order.iter().map( |color| color.name() ).collect();
+        match self.level.gem_order( self.level.lit_gems() ) {
+            GemOrder::Broken => return format!( "Out of order! {}", colors.join( " > " ) ),
+            _ => return format!( "Order {}", colors.join( " > " ) )
+        }
+    }
+
     // Convert the position into a level map coordinate
     fn to_level_pos( &self, pos: &Vec2 ) -> Option<(u32,u32)> {
         let map_x = ( pos.x / 64.0 ) as u32;
@@ -83,10 +243,7 @@ impl GamePage{
 impl page::Page for GamePage {
     
     fn enter(&mut self) {
-        self.last_jewel_ray_count = 0;
-        self.game_state = GameState::ShowingNewLevel( 0.0);
-        self.level = Level::load_level(self.level_no);
-        self.level.tile_movable_effect(TileEffect::Hide );
+        self.start_level( 0.0 );
     }
 
     fn tick( &mut self, display: &glium::Display, config: &config::Config, tile_batcher: &TileBatcher, glyph_brush: &mut GlyphBrush, mouse_state: &MouseState, audio: &audio::Audio, time_in_page: f64, 
@@ -102,27 +259,56 @@ impl page::Page for GamePage {
             }                
         } else {
             if self.tile_move.is_some() {
+                // The ray is shown as it would be with the piece dropped
                 let tile_move = self.tile_move.take().unwrap();
-                self.level.set_front_tile( tile_move.map_x, tile_move.map_y, tile_move.tile );
+                let ( drop_x, drop_y ) = tile_move.drop_at;
+                self.level.set_front_tile( drop_x, drop_y, tile_move.tile );
                 
-                jewel_ray_count = self.level.update_ray( 500, time_in_page );
+                jewel_ray_count = self.level.update_ray( usize::MAX, time_in_page );
                 if jewel_ray_count > self.last_jewel_ray_count {
                     audio.play_sound(audio::SoundEffect::Gem);
                 }                
-                self.level.set_front_tile( tile_move.map_x, tile_move.map_y, Tile::EmptyPiece );
+                self.level.set_front_tile( drop_x, drop_y, Tile::EmptyPiece );
                 self.tile_move = Some( tile_move);
             } else {
-                jewel_ray_count = self.level.update_ray( 500, time_in_page );
-                let solved = jewel_ray_count == self.level.count_jewels();
-                if self.game_state == GameState::Playing && solved {
+                jewel_ray_count = self.level.update_ray( usize::MAX, time_in_page );
+                let solved = self.load_error.is_none() && self.level.is_won( self.level.lit_gems(), self.level.receivers_hit(), self.level.lit_bombs().len() > 0 );
+                if self.game_state == GameState::Playing && self.level.lit_bombs().len() > 0 {
+                    self.fail_level( time_in_page );
+                } else if self.game_state == GameState::Playing && solved {
                     self.game_state = GameState::ShowingSolution( time_in_page );
+                } else if self.game_state == GameState::Playing {
+                    // One-shot gems the beams reach turn into walls unless the layout already wins. The full trace is used
+                    // rather than the animated ray, which can still be paused before a gem, so the same gems are spent as in the solver
+                    let path = beam::trace_beam( &self.level );
+                    if !self.level.is_won_by( &path ) {
+                        for ( x, y ) in self.level.spend_gems( &path.gems ) {
+                            self.level.set_effect( x, y, TileEffect::SizedFadeIn( time_in_page as f32, 1.5, 0.3 ) );
+                        }
+                    }
                 }
             }
         }
+        if self.level.ray_teleported() {
+            audio.play_sound(audio::SoundEffect::Transport);
+        }
+        if self.level.open_doors() != &self.last_open_doors {
+            self.animate_doors( time_in_page );
+        }
+        // The gem order is only announced while the player is moving pieces, not while the solution is shown
+        let gem_order = self.level.gem_order( self.level.lit_gems() );
+        if self.game_state == GameState::Playing && !self.level.metadata().gem_order.is_empty() && gem_order != self.last_gem_order {
+            match gem_order {
+                GemOrder::Kept => audio.play_sound(audio::SoundEffect::OrderKept),
+                GemOrder::Broken => audio.play_sound(audio::SoundEffect::OrderBroken),
+                GemOrder::Pending => {}
+            }
+        }
+        self.last_gem_order = gem_order;
         self.last_jewel_ray_count = jewel_ray_count;
         let map_pos = self.to_level_pos(&mouse_state.pos);
         if let Some( (map_x, map_y ) ) = map_pos {
-            if self.level.front_tile(map_x, map_y).is_movable() {
+            if self.level.front_tile(map_x, map_y).is_movable() || self.level.front_tile(map_x, map_y).is_rotatable() {
                 let tickle_piece = match self.last_map_pos{
                     None => true,
                     Some( (old_x, old_y) ) => {
@@ -149,26 +335,60 @@ impl page::Page for GamePage {
             let delta = tile_move.last_cursor_pos - tile_move.grab_cursor_pos;
             let x = tile_move.map_x as f32 * 64.0+delta.x;
             let y = tile_move.map_y as f32 * 64.0+delta.y;
-            let tile_idx: u8 = tile_move.tile.into();
+            let tile_idx: u8 = tile_move.tile.atlas_index();
             let src = Vec2::new( (tile_idx%14) as f32 / 16.0f32+qtr_pixel, 1.0-((tile_idx/14) as f32 /16f32)-qtr_pixel );
             let ( scaled_pos, scaled_size ) = scale( &Vec2::new( x, config.height() as f32 - y ), &Vec2::new( 64.0, 64.0 ), 1.5f32);
-            tile_batcher.tile(&mut vertices,&scaled_pos, &scaled_size, &src, &Vec2::new( 1.0/16.0-half_pixel, 1.0/16.0-half_pixel ));
+            let ( r, g, b ) = tile_move.tile.tint();
+            tile_batcher.tile_color(&mut vertices,&scaled_pos, &scaled_size, &src, &Vec2::new( 1.0/16.0-half_pixel, 1.0/16.0-half_pixel ), &Vec4::new( r, g, b, 1.0 ));
         }
 
         if self.game_state == GameState::InGameMenu {
             self.ui(tile_batcher, &mut vertices, glyph_brush, config, mouse_state, page_actions);
         }
 
+        if self.game_state == GameState::PackComplete {
+            self.pack_complete_ui(tile_batcher, &mut vertices, glyph_brush, config, mouse_state, page_actions);
+        }
+
         if let GameState::ShowingNewLevel( level_start) = self.game_state {
             let button_width = config.width() as f32 * 0.6f32;
             let button_height = 80.0f32;
             let button_left = ( config.width() as f32 - button_width ) / 2.0;
-            let level_message = "Level ".to_owned() + &self.level_no.to_string();
+            let level_message = if self.load_error.is_some() {
+                "Level ".to_owned() + &self.level_no.to_string() + " failed to load"
+            } else {
+                "Level ".to_owned() + &self.level_no.to_string()
+            };
             
             let x = ( time_in_page - level_start ) - 1.2;
             let y_pos = x.powf(7.0)*x*x*x/(x.signum()+x.powf(3.0));
 
             static_text(tile_batcher, &mut vertices, glyph_brush, config, Vec2::new( (button_left + y_pos as f32 * 800.0) as f32, 800.0),Vec2::new( button_width, button_height ), &level_message );
+
+            // Optional level details fly in with the level number
+            let metadata = self.level.metadata();
+            let mut details: Vec<String> = Vec::new();
+            if !metadata.title.is_empty() {
+                details.push( metadata.title.clone() );
+            }
+            if !metadata.author.is_empty() {
+                details.push( "by ".to_owned() + &metadata.author );
+            }
+            if let Some( par_moves ) = metadata.par_moves {
+                details.push( "Par ".to_owned() + &par_moves.to_string() + " moves" );
+            }
+            if let Some( difficulty ) = metadata.difficulty {
+                details.push( "Difficulty ".to_owned() + &difficulty.to_string() );
+            }
+            for hint in metadata.hints.iter().filter( |hint| !hint.is_empty() ) {
+                details.push( "Hint: ".to_owned() + hint );
+            }
+            let details_width = config.width() as f32 * 0.9f32;
+            let details_left = ( config.width() as f32 - details_width ) / 2.0;
+            for ( idx, detail ) in details.iter().enumerate() {
+                let y = 800.0 - ( idx + 1 ) as f32 * ( button_height + 10.0 );
+                static_text(tile_batcher, &mut vertices, glyph_brush, config, Vec2::new( (details_left + y_pos as f32 * 800.0) as f32, y),Vec2::new( details_width, button_height ), detail );
+            }
         }
 
         if let GameState::ShowingSolution( solution_start ) = self.game_state {
@@ -181,6 +401,30 @@ impl page::Page for GamePage {
             static_text(tile_batcher, &mut vertices, glyph_brush, config, Vec2::new( (button_left + y_pos as f32 * 800.0) as f32, 800.0),Vec2::new( button_width, button_height ), "Level Complete" );
         }
 
+        if let GameState::LevelFailed( failed_at ) = self.game_state {
+            let text_width = config.width() as f32 * 0.6f32;
+            let text_left = ( config.width() as f32 - text_width ) / 2.0;
+            static_text(tile_batcher, &mut vertices, glyph_brush, config, Vec2::new( text_left, 800.0 ),Vec2::new( text_width, 80.0 ), "Level Failed" );
+            if time_in_page - failed_at > 2.0 {
+                self.restart_level();
+            }
+        }
+
+        if self.game_state == GameState::Playing {
+            let text_width = config.width() as f32 * 0.6f32;
+            let text_left = ( config.width() as f32 - text_width ) / 2.0;
+            static_text(tile_batcher, &mut vertices, glyph_brush, config, Vec2::new( text_left, 20.0 ),Vec2::new( text_width, 60.0 ), &self.progress_text( jewel_ray_count ) );
+            if !self.level.metadata().gem_order.is_empty() {
+                static_text(tile_batcher, &mut vertices, glyph_brush, config, Vec2::new( text_left, 100.0 ),Vec2::new( text_width, 60.0 ), &self.gem_order_text() );
+            }
+        }
+
+        if self.game_state == GameState::Playing && self.level.is_ray_looping() {
+            let text_width = config.width() as f32 * 0.6f32;
+            let text_left = ( config.width() as f32 - text_width ) / 2.0;
+            static_text(tile_batcher, &mut vertices, glyph_brush, config, Vec2::new( text_left, 180.0 ),Vec2::new( text_width, 80.0 ), "The beam is looping" );
+        }
+
         
 
         let mut target = display.draw();
@@ -247,7 +491,8 @@ impl page::Page for GamePage {
 
             if (time_in_page - time_started) > 2.5  {
                 self.level.tile_movable_effect(TileEffect::SizedFadeIn( time_in_page as f32, 3.0, 1.0  ) );
-                self.game_state = GameState::Playing;
+                // Nothing to play if the level failed to load. Offer the menu instead
+                self.game_state = if self.load_error.is_some() { GameState::InGameMenu } else { GameState::Playing };
             }
         }
         if let GameState::ShowingSolution( time_started) = self.game_state  {
@@ -259,9 +504,7 @@ impl page::Page for GamePage {
                 if back_depth == 1.0 {
                     self.level_no += 1;
                     page_actions.push( PageAction::OpenLevel(self.level_no));
-                    self.level = Level::load_level(self.level_no);
-                    self.game_state = GameState::ShowingNewLevel( time_in_page );
-                    self.level.tile_movable_effect(TileEffect::Hide );
+                    self.start_level( time_in_page );
                 }
             }
         }
@@ -275,22 +518,33 @@ impl page::Page for GamePage {
     }
 
 
-    fn mouse_click( &mut self, pressed: bool, pos: Vec2 ) -> PageAction{
+    fn mouse_click( &mut self, button: MouseButton, pressed: bool, pos: Vec2 ) -> PageAction{
         if self.game_state == GameState::Playing {
             if pressed {
                 let map_pos = self.to_level_pos(&pos);
                 if let Some( (map_x, map_y) ) =  map_pos {
                     let tile = self.level.front_tile(map_x, map_y);
                     println!( "Piece at ( {},{} ) is {:?} ( movable = {} )", map_x, map_y, tile, tile.is_movable() );
-                    if tile.is_movable() {
+                    if tile.is_rotatable() && self.tile_move.is_none() {
+                        // Left click turns the mirror clockwise and right click turns it back
+                        self.history.push( UndoStep{ from: ( map_x, map_y ), to: ( map_x, map_y ), tile, attempt: self.level.attempt().clone() } );
+                        self.level.set_front_tile( map_x, map_y, tile.rotated( button == MouseButton::Left ) );
+                    } else if tile.is_movable() && button == MouseButton::Left {
+                        let open_doors = self.level.open_doors().clone();
                         self.level.set_front_tile( map_x, map_y, Tile::EmptyPiece );
-                        self.tile_move = Some( TileMove{ tile, map_x, map_y, grab_cursor_pos: pos, last_cursor_pos: pos});
+                        self.tile_move = Some( TileMove{ tile, from: ( map_x, map_y ), map_x, map_y, drop_at: ( map_x, map_y ), open_doors, grab_cursor_pos: pos, last_cursor_pos: pos});
                     }
                 }
             } else {
-                if self.tile_move.is_some() {
+                if self.tile_move.is_some() && button == MouseButton::Left {
+                    // A piece let go over an open door goes back to the last tile it crossed before the door
                     let tile_move = self.tile_move.take().unwrap();
-                    self.level.set_front_tile( tile_move.map_x, tile_move.map_y, tile_move.tile );
+                    let ( drop_x, drop_y ) = tile_move.drop_at;
+                    self.level.set_front_tile( drop_x, drop_y, tile_move.tile );
+                    // One-shot gems are only spent once a piece is dropped so the attempt is as it was when it was picked up
+                    if tile_move.from != tile_move.drop_at {
+                        self.history.push( UndoStep{ from: tile_move.from, to: tile_move.drop_at, tile: tile_move.tile, attempt: self.level.attempt().clone() } );
+                    }
                 }
             }
         } 
@@ -301,7 +555,7 @@ impl page::Page for GamePage {
         if self.tile_move.is_some() {
             let mut hit: u32 = 0;
             let mut moved: u32 = 0;
-            let mut tile_move = self.tile_move.unwrap();
+            let mut tile_move = self.tile_move.take().unwrap();
             let old_map_x = tile_move.map_x;
             let old_map_y = tile_move.map_y;
             for t in 0..15{ 
@@ -309,27 +563,23 @@ impl page::Page for GamePage {
                 // Should the map position change
                 let mut delta = tile_move.last_cursor_pos - tile_move.grab_cursor_pos;
                 if delta.x > 0.0 {
-                    if tile_move.map_x == self.level.width-1 || self.level.front_tile(tile_move.map_x+1, tile_move.map_y) != Tile::EmptyPiece || 
-                        self.level.back_tile(tile_move.map_x+1, tile_move.map_y) == Tile::EmptyPiece { 
+                    if tile_move.map_x == self.level.width-1 || !self.level.is_passable_tile(tile_move.map_x+1, tile_move.map_y, &tile_move.open_doors) { 
                         delta.x = 0.0;
                         hit = hit | 0x01;
                     }
                 } else if delta.x < 0.0 {
-                    if tile_move.map_x == 0 || self.level.front_tile(tile_move.map_x-1, tile_move.map_y) != Tile::EmptyPiece ||
-                        self.level.back_tile(tile_move.map_x-1, tile_move.map_y) == Tile::EmptyPiece{ 
+                    if tile_move.map_x == 0 || !self.level.is_passable_tile(tile_move.map_x-1, tile_move.map_y, &tile_move.open_doors) { 
                         delta.x = 0.0;
                         hit = hit | 0x02;
                     }
                 }
                 if delta.y > 0.0 {
-                    if tile_move.map_y == self.level.height-1 || self.level.front_tile(tile_move.map_x, tile_move.map_y+1) != Tile::EmptyPiece ||
-                        self.level.back_tile(tile_move.map_x, tile_move.map_y+1) == Tile::EmptyPiece{
+                    if tile_move.map_y == self.level.height-1 || !self.level.is_passable_tile(tile_move.map_x, tile_move.map_y+1, &tile_move.open_doors) {
                         delta.y = 0.0;
                         hit = hit | 0x04;
                     }
                 } else if delta.y < 0.0 {
-                    if tile_move.map_y == 0 || self.level.front_tile(tile_move.map_x, tile_move.map_y-1) != Tile::EmptyPiece || 
-                        self.level.back_tile(tile_move.map_x, tile_move.map_y-1) == Tile::EmptyPiece { 
+                    if tile_move.map_y == 0 || !self.level.is_passable_tile(tile_move.map_x, tile_move.map_y-1, &tile_move.open_doors) { 
                         delta.y = 0.0;
                         hit = hit | 0x08;
                     }
@@ -359,10 +609,13 @@ impl page::Page for GamePage {
                 }
                 tile_move.last_cursor_pos = tile_move.grab_cursor_pos + delta;
             }
-            if self.level.front_tile(tile_move.map_x, tile_move.map_y) != Tile::EmptyPiece {
+            if !self.level.is_passable_tile(tile_move.map_x, tile_move.map_y, &tile_move.open_doors) {
                 tile_move.map_x = old_map_x;
                 tile_move.map_y = old_map_y;
             }
+            if self.level.is_open_tile(tile_move.map_x, tile_move.map_y) {
+                tile_move.drop_at = ( tile_move.map_x, tile_move.map_y );
+            }
             self.tile_move = Some( tile_move );
             if (moved & hit) != 0 {
                 audio.play_sound(audio::SoundEffect::Ping);
@@ -379,6 +632,13 @@ impl page::Page for GamePage {
                     self.game_state = GameState::Playing;
                 }
             }
+            if self.game_state == GameState::Playing && self.tile_move.is_none() {
+                if key == VirtualKeyCode::Back || key == VirtualKeyCode::Z {
+                    self.undo();
+                } else if key == VirtualKeyCode::R {
+                    self.restart_level();
+                }
+            }
         }
         return PageAction::None;
     }
@@ -0,0 +1,314 @@
+use super::level::{BeamDirection, Level, LevelMetadata, Tile};
+use super::solver::Solver;
+use super::beam;
+
+// Small xorshift generator so a seed always produces the same level on every platform
+pub struct Rng{
+    state: u64
+}
+
+impl Rng{
+    pub fn new( seed: u64 ) -> Rng {
+        // Zero is a fixed point of xorshift so mix the seed first
+        return Rng{ state: seed.wrapping_mul( 0x9E37_79B9_7F4A_7C15 ) | 1 };
+    }
+
+    pub fn next_u64( &mut self ) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        return self.state;
+    }
+
+    // Uniform value in min..=max
+    pub fn range( &mut self, min: u32, max: u32 ) -> u32 {
+        return min + ( self.next_u64() % ( ( max - min ) as u64 + 1 ) ) as u32;
+    }
+
+    pub fn chance( &mut self, percent: u32 ) -> bool {
+        return self.range( 0, 99 ) < percent;
+    }
+}
+
+pub struct GeneratorSettings{
+    pub width: u32,
+    pub height: u32,
+    pub turns: u32,                 // mirrors the beam passes on the way to the last wall
+    pub gems: u32,
+    pub movable_percent: u32,       // chance that a mirror on the beam can be moved
+    pub teleport_percent: u32,      // chance that the beam is sent through a teleport pair
+    pub decoys: u32,                // movable mirrors that are not part of the solution
+    pub blockers: u32,
+    pub scramble_moves: u32
+}
+
+impl Default for GeneratorSettings {
+    fn default() -> Self {
+        return GeneratorSettings{ width: 11, height: 15, turns: 4, gems: 3, movable_percent: 60, teleport_percent: 30, decoys: 1, blockers: 4, scramble_moves: 4 };
+    }
+}
+
+const MAX_ATTEMPTS: u32 = 1000;
+
+const DIRECTIONS: [BeamDirection; 4] = [ BeamDirection::Up, BeamDirection::Right, BeamDirection::Down, BeamDirection::Left ];
+
+fn step( x: u32, y: u32, direction: BeamDirection ) -> (u32,u32) {
+    match direction {
+        BeamDirection::Up => return ( x, y.wrapping_sub( 1 ) ),
+        BeamDirection::Down => return ( x, y+1 ),
+        BeamDirection::Left => return ( x.wrapping_sub( 1 ), y ),
+        BeamDirection::Right => return ( x+1, y )
+    }
+}
+
+fn turn( direction: BeamDirection, clockwise: bool ) -> BeamDirection {
+    let idx = DIRECTIONS.iter().position( |d| *d == direction ).unwrap();
+    return DIRECTIONS[ if clockwise { ( idx+1 ) % 4 } else { ( idx+3 ) % 4 } ];
+}
+
+fn source_tile( direction: BeamDirection ) -> Tile {
+    match direction {
+        BeamDirection::Up => return Tile::RaySourceUp,
+        BeamDirection::Down => return Tile::RaySourceDown,
+        BeamDirection::Left => return Tile::RaySourceLeft,
+        BeamDirection::Right => return Tile::RaySourceRight
+    }
+}
+
+// The movable and immovable mirror that turn a beam travelling in direction from to direction to
+fn mirrors_for_turn( from: BeamDirection, to: BeamDirection ) -> Option<(Tile,Tile)> {
+    match ( from, to ) {
+        ( BeamDirection::Up, BeamDirection::Right ) | ( BeamDirection::Left, BeamDirection::Down ) => return Some( ( Tile::MovableTopLeft, Tile::ImmovableTopLeft ) ),
+        ( BeamDirection::Right, BeamDirection::Down ) | ( BeamDirection::Up, BeamDirection::Left ) => return Some( ( Tile::MovableTopRight, Tile::ImmovableTopRight ) ),
+        ( BeamDirection::Down, BeamDirection::Right ) | ( BeamDirection::Left, BeamDirection::Up ) => return Some( ( Tile::MovableBottomLeft, Tile::ImmovableBottomLeft ) ),
+        ( BeamDirection::Down, BeamDirection::Left ) | ( BeamDirection::Right, BeamDirection::Up ) => return Some( ( Tile::MovableBottomRight, Tile::ImmovableBottomRight ) ),
+        _ => return None
+    }
+}
+
+const MOVABLE_PIECES: [Tile; 4] = [ Tile::MovableTopLeft, Tile::MovableTopRight, Tile::MovableBottomLeft, Tile::MovableBottomRight ];
+const FLOORS: [Tile; 3] = [ Tile::Floor1, Tile::Floor2, Tile::Floor3 ];
+
+struct Board{
+    level: Level,
+    used: Vec<bool>             // tiles the solved beam relies on
+}
+
+impl Board{
+    fn new( width: u32, height: u32, rng: &mut Rng ) -> Board {
+        let layer_size = ( width*height ) as usize;
+        let mut back: Vec<u8> = Vec::with_capacity( layer_size );
+        let mut front: Vec<u8> = Vec::with_capacity( layer_size );
+        for y in 0..height {
+            for x in 0..width {
+                // Mostly the plain floor with the odd variation
+                let floor = if rng.chance( 80 ) { Tile::Floor2 } else { FLOORS[ rng.range( 0, 2 ) as usize ] };
+                back.push( floor.into() );
+                let wall = match ( x == 0, x == width-1, y == 0, y == height-1 ) {
+                    ( true, _, true, _ ) => Tile::WallTurnTopLeft,
+                    ( _, true, true, _ ) => Tile::WallTurnTopRight,
+                    ( true, _, _, true ) => Tile::WallTurnBottomLeft,
+                    ( _, true, _, true ) => Tile::WallTurnBottomRight,
+                    ( _, _, true, _ ) | ( _, _, _, true ) => Tile::WallHorizontal,
+                    ( true, _, _, _ ) | ( _, true, _, _ ) => Tile::WallVertical,
+                    _ => Tile::EmptyPiece
+                };
+                front.push( wall.into() );
+            }
+        }
+        let level = Level::from_layers( width, height, true, back, front, vec![ Tile::EmptyPiece.into(); layer_size ] ).unwrap();
+        return Board{ level, used: vec![ false; layer_size ] };
+    }
+
+    fn is_free( &self, x: u32, y: u32 ) -> bool {
+        return x < self.level.width && y < self.level.height && self.level.front_tile( x, y ) == Tile::EmptyPiece && !self.used[ ( y*self.level.width+x ) as usize ];
+    }
+
+    fn set( &mut self, x: u32, y: u32, tile: Tile ) {
+        self.level.set_front_tile( x, y, tile );
+        self.used[ ( y*self.level.width+x ) as usize ] = true;
+    }
+
+    fn random_free_tile( &self, rng: &mut Rng ) -> Option<(u32,u32)> {
+        let free: Vec<(u32,u32)> = ( 0..self.level.height ).flat_map( |y| ( 0..self.level.width ).map( move |x| ( x, y ) ) )
+            .filter( |( x, y )| self.is_free( *x, *y ) ).collect();
+        if free.len() == 0 {
+            return None;
+        }
+        return Some( free[ rng.range( 0, free.len() as u32 - 1 ) as usize ] );
+    }
+
+    // Number of free tiles in a straight line from ( x, y ) in the given direction
+    fn free_run( &self, x: u32, y: u32, direction: BeamDirection ) -> u32 {
+        let ( mut x, mut y ) = ( x, y );
+        let mut run = 0;
+        loop {
+            let next = step( x, y, direction );
+            if !self.is_free( next.0, next.1 ) {
+                return run;
+            }
+            x = next.0;
+            y = next.1;
+            run += 1;
+        }
+    }
+}
+
+// Lay out a beam from a source through mirrors and teleports and put the gems on it. The movable
+// pieces are left in their solved places
+fn build_solved( settings: &GeneratorSettings, rng: &mut Rng ) -> Option<Level> {
+    let mut board = Board::new( settings.width, settings.height, rng );
+    let ( mut x, mut y ) = board.random_free_tile( rng )?;
+    let mut direction = DIRECTIONS[ rng.range( 0, 3 ) as usize ];
+    if board.free_run( x, y, direction ) < 2 {
+        return None;
+    }
+    board.set( x, y, source_tile( direction ) );
+
+    let mut beam_tiles: Vec<(u32,u32)> = Vec::new();
+    let mut teleported = false;
+    for segment in 0..=settings.turns {
+        let run = board.free_run( x, y, direction );
+        if run < 2 {
+            return None;
+        }
+        let length = if segment == settings.turns { run } else { rng.range( 2, run ) };
+        for _ in 0..length {
+            let next = step( x, y, direction );
+            x = next.0;
+            y = next.1;
+            beam_tiles.push( ( x, y ) );
+            board.used[ ( y*board.level.width+x ) as usize ] = true;
+        }
+        if segment == settings.turns {
+            break;
+        }
+        // The last tile of the run turns the beam. It is no longer a tile the beam passes over
+        beam_tiles.pop();
+        if !teleported && rng.chance( settings.teleport_percent ) {
+            // The beam leaves the partner teleport in the direction it entered
+            let ( exit_x, exit_y ) = board.random_free_tile( rng )?;
+            if board.free_run( exit_x, exit_y, direction ) < 2 {
+                return None;
+            }
+            board.set( x, y, Tile::RayTeleport1 );
+            board.set( exit_x, exit_y, Tile::RayTeleport1 );
+            x = exit_x;
+            y = exit_y;
+            teleported = true;
+            continue;
+        }
+        let turn_to = turn( direction, rng.chance( 50 ) );
+        let ( movable, immovable ) = mirrors_for_turn( direction, turn_to )?;
+        board.set( x, y, if rng.chance( settings.movable_percent ) { movable } else { immovable } );
+        direction = turn_to;
+    }
+
+    if beam_tiles.len() < settings.gems as usize {
+        return None;
+    }
+    for _ in 0..settings.gems {
+        let ( gem_x, gem_y ) = beam_tiles.remove( rng.range( 0, beam_tiles.len() as u32 - 1 ) as usize );
+        board.level.set_front_tile( gem_x, gem_y, Tile::GemRed );
+    }
+    for _ in 0..settings.decoys {
+        let ( decoy_x, decoy_y ) = board.random_free_tile( rng )?;
+        board.set( decoy_x, decoy_y, MOVABLE_PIECES[ rng.range( 0, 3 ) as usize ] );
+    }
+    for _ in 0..settings.blockers {
+        let ( blocker_x, blocker_y ) = board.random_free_tile( rng )?;
+        board.set( blocker_x, blocker_y, Tile::WallBlocker );
+    }
+
+    // The beam crosses its own path and walls in ways the layout above does not track so
+    // check the solved level the same way the game would
+    let level = board.level;
+    if level.count_jewels() == 0 || !level.is_won_by( &beam::trace_beam( &level ) ) {
+        return None;
+    }
+    return Some( level );
+}
+
+// Drag random pieces away from the solved places. Every drag can be undone by dragging the piece back
+// so the scrambled level can always be solved
+fn scramble( level: &Level, moves: u32, rng: &mut Rng ) -> Option<Level> {
+    let mut solver = Solver::new( level );
+    let solved = Solver::initial_state( level );
+    if solved.len() == 0 {
+        return None;
+    }
+    let mut state = solved.clone();
+    let mut drags = 0;
+    while drags < moves || solver.is_solved( &state ) {
+        if drags >= moves*4 {
+            return None;
+        }
+        let next = solver.next_states( &state );
+        if next.len() == 0 {
+            return None;
+        }
+        state = next[ rng.range( 0, next.len() as u32 - 1 ) as usize ].0.clone();
+        drags += 1;
+    }
+
+    let mut scrambled = level.clone();
+    for ( offset, tile ) in solved.iter() {
+        let ( x, y ) = solver.position( *offset );
+        scrambled.set_front_tile( x, y, Tile::EmptyPiece );
+        scrambled.set_solution_tile( x, y, *tile );
+    }
+    for ( offset, tile ) in state.iter() {
+        let ( x, y ) = solver.position( *offset );
+        scrambled.set_front_tile( x, y, *tile );
+    }
+    return Some( scrambled );
+}
+
+// Build a level from a seed. The same seed and settings always give the same level
+pub fn generate_level( seed: u64, settings: &GeneratorSettings ) -> Option<Level> {
+    let mut rng = Rng::new( seed );
+    for _ in 0..MAX_ATTEMPTS {
+        let solved = match build_solved( settings, &mut rng ) {
+            Some( solved ) => solved,
+            None => continue
+        };
+        if let Some( mut level ) = scramble( &solved, settings.scramble_moves, &mut rng ) {
+            level.set_metadata( LevelMetadata{ title: format!( "Generated {}", seed ), ..LevelMetadata::default() } );
+            return Some( level );
+        }
+    }
+    return None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::level::SolutionStatus;
+    use super::super::solver::{solve_level, SolveResult};
+    use super::{generate_level, GeneratorSettings};
+
+    fn settings() -> GeneratorSettings {
+        return GeneratorSettings{ width: 8, height: 8, turns: 3, gems: 2, movable_percent: 60, teleport_percent: 30, decoys: 1, blockers: 2, scramble_moves: 3 };
+    }
+
+    #[test]
+    fn seeds_always_give_the_same_level() {
+        for seed in 1..5 {
+            let first = generate_level( seed, &settings() ).unwrap();
+            let second = generate_level( seed, &settings() ).unwrap();
+            assert_eq!( first.to_bytes().unwrap(), second.to_bytes().unwrap() );
+        }
+    }
+
+    // The stored solution wins and the solver finds a route within the drags used to scramble the level
+    #[test]
+    fn generated_levels_are_solvable() {
+        for seed in 1..5 {
+            let level = generate_level( seed, &settings() ).unwrap();
+            assert_eq!( level.verify_solution(), SolutionStatus::Solved, "seed {}", seed );
+            match solve_level( &level, 100_000 ) {
+                SolveResult::Solved( moves ) => assert!( moves.len() >= 1 && moves.len() <= 3*4, "seed {}", seed ),
+                result => panic!( "seed {}: {:?}", seed, result )
+            }
+        }
+    }
+}
@@ -1,10 +1,15 @@
 use num_enum::IntoPrimitive;
 use num_enum::TryFromPrimitive;
-use std::{collections::HashMap, convert::TryFrom};
+use std::{collections::HashMap, convert::TryFrom, fmt};
 use std::{fs, u32};
-use std::env;
+use std::path::{Path, PathBuf};
+use std::io;
+use serde::{Deserialize, Serialize};
 use super::{Vec2};
 use super::tile_batcher::*;
+use super::level_text;
+use super::assets::Assets;
+use super::beam::{self, BeamColor, BeamEnd, BeamEvent, BeamPath};
 
 #[derive(Clone, Copy)]
 #[derive(Debug, Eq, PartialEq, Hash, TryFromPrimitive)]
@@ -57,7 +62,55 @@ pub enum Tile {
     GemPurple = 52,
     Floor1 = 53,
     Floor2 = 54,
-    Floor3 =55
+    Floor3 =55,
+    // Coloured sources have no art of their own. They are drawn with the white source tiles tinted to the beam colour
+    RaySourceRedUp = 56,
+    RaySourceRedRight = 57,
+    RaySourceRedDown = 58,
+    RaySourceRedLeft = 59,
+    RaySourceGreenUp = 60,
+    RaySourceGreenRight = 61,
+    RaySourceGreenDown = 62,
+    RaySourceGreenLeft = 63,
+    RaySourceYellowUp = 64,
+    RaySourceYellowRight = 65,
+    RaySourceYellowDown = 66,
+    RaySourceYellowLeft = 67,
+    RaySourcePurpleUp = 68,
+    RaySourcePurpleRight = 69,
+    RaySourcePurpleDown = 70,
+    RaySourcePurpleLeft = 71,
+    // Splitters send a beam out both sides at right angles to the way it came in
+    MovableSplitter = 72,
+    ImmovableSplitter = 73,
+    // Rotatable mirrors stay where they are and turn a quarter at a time when clicked
+    RotatableTopLeft = 74,
+    RotatableTopRight = 75,
+    RotatableBottomRight = 76,
+    RotatableBottomLeft = 77,
+    // Teleport channels beyond the first two. Each channel is a pair of teleports
+    RayTeleport3 = 78,
+    RayTeleport4 = 79,
+    RayTeleport5 = 80,
+    RayTeleport6 = 81,
+    // While a beam crosses a switch the doors of the same channel are open
+    SwitchA = 82,
+    SwitchB = 83,
+    DoorA = 84,
+    DoorB = 85,
+    // The level fails if a beam crosses a bomb
+    Bomb = 86,
+    // Receivers absorb the beam and only count when the beam comes in through the open side
+    ReceiverTop = 87,
+    ReceiverRight = 88,
+    ReceiverBottom = 89,
+    ReceiverLeft = 90,
+    // Lit by white beams like other gems. Once lit it blocks beams for the rest of the attempt
+    GemOneShot = 91,
+    // Pieces that can be dragged like the movable mirrors but block or filter the beam
+    MovableBlocker = 92,
+    MovablePassHorizontal = 93,
+    MovablePassVertical = 94
 }
 
 impl Default for Tile {
@@ -69,36 +122,197 @@ impl Default for Tile {
 impl Tile{
     pub fn is_movable( &self ) -> bool {
         return ( *self == Tile::MovableBottomLeft ) || ( *self == Tile::MovableBottomRight || 
-                ( *self == Tile::MovableTopLeft ) || ( *self == Tile::MovableTopRight ) );
+                ( *self == Tile::MovableTopLeft ) || ( *self == Tile::MovableTopRight ) || ( *self == Tile::MovableSplitter ) ||
+                ( *self == Tile::MovableBlocker ) || ( *self == Tile::MovablePassHorizontal ) || ( *self == Tile::MovablePassVertical ) );
+    }
+
+    pub fn is_rotatable( &self ) -> bool {
+        return *self == Tile::RotatableTopLeft || *self == Tile::RotatableTopRight || *self == Tile::RotatableBottomRight || *self == Tile::RotatableBottomLeft;
+    }
+
+    // The next orientation of a rotatable mirror. Clockwise goes top left, top right, bottom right, bottom left
+    pub fn rotated( &self, clockwise: bool ) -> Tile {
+        let order = [ Tile::RotatableTopLeft, Tile::RotatableTopRight, Tile::RotatableBottomRight, Tile::RotatableBottomLeft ];
+        match order.iter().position( |tile| tile == self ) {
+            Some( idx ) => return order[ if clockwise { ( idx+1 ) % 4 } else { ( idx+3 ) % 4 } ],
+            None => return *self
+        }
+    }
+
+    pub fn is_splitter( &self ) -> bool {
+        return *self == Tile::MovableSplitter || *self == Tile::ImmovableSplitter;
     }
 
     pub fn is_ray_source( &self ) -> bool {
-        return ( *self == Tile::RaySourceUp ) || ( *self == Tile::RaySourceRight || 
-                ( *self == Tile::RaySourceDown ) || ( *self == Tile::RaySourceLeft ) );
+        return beam::source_beam( *self ).is_some();
+    }
+
+    // The tile in the atlas used to draw this tile
+    pub fn atlas_index( &self ) -> u8 {
+        match beam::source_beam( *self ) {
+            Some( ( BeamDirection::Up, _ ) ) => return Tile::RaySourceUp.into(),
+            Some( ( BeamDirection::Right, _ ) ) => return Tile::RaySourceRight.into(),
+            Some( ( BeamDirection::Down, _ ) ) => return Tile::RaySourceDown.into(),
+            Some( ( BeamDirection::Left, _ ) ) => return Tile::RaySourceLeft.into(),
+            None => {}
+        }
+        if self.is_splitter() {
+            return Tile::RayCross.into();
+        }
+        match self {
+            Tile::RotatableTopLeft => return Tile::ImmovableTopLeft.into(),
+            Tile::RotatableTopRight => return Tile::ImmovableTopRight.into(),
+            Tile::RotatableBottomRight => return Tile::ImmovableBottomRight.into(),
+            Tile::RotatableBottomLeft => return Tile::ImmovableBottomLeft.into(),
+            Tile::RayTeleport3 | Tile::RayTeleport5 => return Tile::RayTeleport1.into(),
+            Tile::RayTeleport4 | Tile::RayTeleport6 => return Tile::RayTeleport2.into(),
+            Tile::SwitchA | Tile::SwitchB => return Tile::GemYellow.into(),
+            Tile::DoorA | Tile::DoorB => return Tile::WallBlocker.into(),
+            Tile::Bomb => return Tile::GemPurple.into(),
+            Tile::GemOneShot => return Tile::GemGreen.into(),
+            Tile::MovableBlocker => return Tile::WallBlocker.into(),
+            Tile::MovablePassHorizontal => return Tile::PassHorizontal.into(),
+            Tile::MovablePassVertical => return Tile::PassVertical.into(),
+            Tile::ReceiverTop => return Tile::RaySourceUp.into(),
+            Tile::ReceiverRight => return Tile::RaySourceRight.into(),
+            Tile::ReceiverBottom => return Tile::RaySourceDown.into(),
+            Tile::ReceiverLeft => return Tile::RaySourceLeft.into(),
+            _ => return ( *self ).into()
+        }
+    }
+
+    // Colour the atlas tile is drawn with. Tiles that borrow the art of another tile are tinted to tell them apart
+    pub fn tint( &self ) -> ( f32, f32, f32 ) {
+        if let Some( ( _, color ) ) = beam::source_beam( *self ) {
+            return color.rgb();
+        }
+        if *self == Tile::ImmovableSplitter {
+            return ( 0.6, 0.6, 0.6 );
+        }
+        if self.is_rotatable() {
+            return ( 0.6, 0.8, 1.0 );
+        }
+        match self {
+            Tile::RayTeleport3 => return ( 1.0, 0.6, 0.2 ),
+            Tile::RayTeleport4 => return ( 0.4, 1.0, 0.5 ),
+            Tile::RayTeleport5 => return ( 1.0, 0.5, 0.8 ),
+            Tile::RayTeleport6 => return ( 0.4, 0.9, 1.0 ),
+            Tile::SwitchA | Tile::DoorA => return ( 1.0, 0.7, 0.2 ),
+            Tile::SwitchB | Tile::DoorB => return ( 0.3, 0.9, 0.9 ),
+            Tile::Bomb => return ( 0.35, 0.3, 0.3 ),
+            Tile::GemOneShot => return ( 0.7, 0.9, 1.0 ),
+            Tile::MovableBlocker | Tile::MovablePassHorizontal | Tile::MovablePassVertical => return ( 1.0, 0.85, 0.55 ),
+            Tile::ReceiverTop | Tile::ReceiverRight | Tile::ReceiverBottom | Tile::ReceiverLeft => return ( 0.55, 0.75, 0.55 ),
+            _ => {}
+        }
+        return ( 1.0, 1.0, 1.0 );
     }
 
     pub fn is_ray_blocker( &self, direction: BeamDirection ) -> bool {
         if direction == BeamDirection::Down || direction == BeamDirection::Up {
-            if *self == Tile::PassHorizontal  {
+            if *self == Tile::PassHorizontal || *self == Tile::MovablePassHorizontal {
                 return true;
             }
         } else {
-            if *self == Tile::PassVertical  {
+            if *self == Tile::PassVertical || *self == Tile::MovablePassVertical {
                 return true;
             }
         }
-        return *self == Tile::WallBlocker || *self == Tile::WallHorizontal || *self == Tile::WallTDown || *self == Tile::WallTerminatorBottom ||
+        return *self == Tile::WallBlocker || *self == Tile::MovableBlocker || *self == Tile::WallHorizontal || *self == Tile::WallTDown || *self == Tile::WallTerminatorBottom ||
             *self == Tile::WallTerminatorLeft || *self == Tile::WallTerminatorRight || *self == Tile::WallTerminatorTop ||
             *self == Tile::WallTLeft || *self == Tile::WallTRight || *self == Tile::WallTUp || *self == Tile::WallTurnBottomLeft ||
             *self == Tile::WallTurnBottomRight || *self == Tile::WallTurnTopLeft || *self == Tile::WallTurnTopRight || *self == Tile::WallVertical;
     }
 
     pub fn is_a_gem( &self ) -> bool {
-        return *self == Tile::GemGreen || *self == Tile::GemRed || *self == Tile::GemYellow || *self == Tile::GemPurple;
+        return *self == Tile::GemGreen || *self == Tile::GemRed || *self == Tile::GemYellow || *self == Tile::GemPurple || *self == Tile::GemOneShot;
+    }
+
+    pub fn gem_color( &self ) -> Option<GemColor> {
+        match self {
+            Tile::GemRed => return Some( GemColor::Red ),
+            Tile::GemGreen => return Some( GemColor::Green ),
+            Tile::GemYellow => return Some( GemColor::Yellow ),
+            Tile::GemPurple => return Some( GemColor::Purple ),
+            _ => return None
+        }
+    }
+
+    // The direction a beam must be travelling in to enter a receiver through its open side
+    pub fn receiver_entry( &self ) -> Option<BeamDirection> {
+        match self {
+            Tile::ReceiverTop => return Some( BeamDirection::Down ),
+            Tile::ReceiverRight => return Some( BeamDirection::Left ),
+            Tile::ReceiverBottom => return Some( BeamDirection::Up ),
+            Tile::ReceiverLeft => return Some( BeamDirection::Right ),
+            _ => return None
+        }
+    }
+
+    pub fn switch_channel( &self ) -> Option<u32> {
+        match self {
+            Tile::SwitchA => return Some( 1 ),
+            Tile::SwitchB => return Some( 2 ),
+            _ => return None
+        }
+    }
+
+    // Doors are not ray blockers themselves. The tracer decides if a door is open
+    pub fn door_channel( &self ) -> Option<u32> {
+        match self {
+            Tile::DoorA => return Some( 1 ),
+            Tile::DoorB => return Some( 2 ),
+            _ => return None
+        }
     }
 
     pub fn is_teleport( &self ) -> bool {
-        return *self == Tile::RayTeleport1 || *self == Tile::RayTeleport2;
+        return self.teleport_channel().is_some();
+    }
+
+    // Teleports on the same channel are linked. A beam entering one leaves from the other. Each channel has its own
+    // tile so a level can use at most six channels, RayTeleport1 to RayTeleport6
+    pub fn teleport_channel( &self ) -> Option<u32> {
+        match self {
+            Tile::RayTeleport1 => return Some( 1 ),
+            Tile::RayTeleport2 => return Some( 2 ),
+            Tile::RayTeleport3 => return Some( 3 ),
+            Tile::RayTeleport4 => return Some( 4 ),
+            Tile::RayTeleport5 => return Some( 5 ),
+            Tile::RayTeleport6 => return Some( 6 ),
+            _ => return None
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LevelError{
+    MissingFile( PathBuf ),
+    BadHeader,
+    WrongLength{ expected: usize, actual: usize },
+    UnknownTile{ offset: usize, value: u8 },
+    Parse{ line: usize, message: String },
+    BadMetadata( String ),
+    BadPack( String ),
+    UnpairedTeleport{ channel: u32, count: usize },
+    BadTeleportExit( u32, u32 ),
+    BadGemOrder( GemColor )                 // the colour is listed twice in the gem order
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LevelError::MissingFile( path ) => write!( f, "level file {:?} could not be read", path ),
+            LevelError::BadHeader => write!( f, "level header is missing or invalid" ),
+            LevelError::WrongLength{ expected, actual } => write!( f, "level data is {} bytes, expected {}", actual, expected ),
+            LevelError::UnknownTile{ offset, value } => write!( f, "unknown tile {} at byte {}", value, offset ),
+            LevelError::Parse{ line, message } => write!( f, "line {}: {}", line, message ),
+            LevelError::BadMetadata( message ) => write!( f, "level metadata is invalid: {}", message ),
+            LevelError::BadPack( message ) => write!( f, "level pack is invalid: {}", message ),
+            LevelError::UnpairedTeleport{ channel, count } => write!( f, "teleport channel {} has {} teleports, expected 2", channel, count ),
+            LevelError::BadTeleportExit( x, y ) => write!( f, "teleport exit at ( {},{} ) is not on a teleport", x, y ),
+            LevelError::BadGemOrder( color ) => write!( f, "gem order lists {} more than once", color.name() ),
+        }
     }
 }
 
@@ -107,7 +321,9 @@ pub enum TileEffect{
     None,
     Hide,
     Punch(f32,Vec2),
-    SizedFadeIn(f32,f32,f32)    // start time, start_scale, duration
+    SizedFadeIn(f32,f32,f32),   // start time, start_scale, duration
+    Flash(f32,f32),             // start time, duration
+    Fade(f32,f32,f32,f32)       // start time, start alpha, end alpha, duration. The tile stays at the end alpha
 }
 
 pub fn apply_tile_effect( tile_effect: &TileEffect, time_in_level: f32, pos: &Vec2, size: &Vec2, alpha: f32 ) -> ( Vec2, Vec2, f32 ) {
@@ -128,6 +344,18 @@ pub fn apply_tile_effect( tile_effect: &TileEffect, time_in_level: f32, pos: &Ve
             let scaled_alpha = time_in_effect.min( 1.0)*alpha;
             return (scaled_pos,  scaled_size, scaled_alpha );
         }
+        TileEffect::Flash( time_started, duration) => {
+            let time_in_effect = time_in_level - time_started;
+            if time_in_effect > *duration {
+                return (pos.clone(), size.clone(), alpha );
+            }
+            let ( scaled_pos, scaled_size ): ( Vec2, Vec2) = scale(pos,size,1.0 + 0.3*(time_in_effect*20.0).sin().abs() );
+            return (scaled_pos, scaled_size, alpha * ( 0.5 + 0.5*(time_in_effect*20.0).cos() ) );
+        }
+        TileEffect::Fade( time_started, start_alpha, end_alpha, duration) => {
+            let progress = ( ( time_in_level - time_started ) / duration ).max( 0.0 ).min( 1.0 );
+            return (pos.clone(), size.clone(), alpha * ( start_alpha + ( end_alpha - start_alpha ) * progress ) );
+        }
     }
 }
 
@@ -141,8 +369,115 @@ pub fn tile_effect_to_offset( tile_effect: &TileEffect, time_in_level: f32 ) ->
             direction * scale as f32
         }
         TileEffect::SizedFadeIn( time_started, start_scale, duration) => { Vec2::new( 0.0, 0.0 )},
+        TileEffect::Flash( _, _ ) => { Vec2::new( 0.0, 0.0 )},
+        TileEffect::Fade( _, _, _, _ ) => { Vec2::new( 0.0, 0.0 )},
+    }
+}
+// What happens to a beam that runs off the edge of the level
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EdgeBehaviour{
+    Wrap,                                   // the beam comes back in on the opposite edge
+    Absorb,                                 // the beam stops
+    Reflect                                 // the beam turns round and comes back the way it came
+}
+
+impl Default for EdgeBehaviour {
+    fn default() -> Self {
+        return EdgeBehaviour::Wrap;
+    }
+}
+
+// What the beams must do to win a level. A beam crossing a bomb always loses
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WinCondition{
+    AllGems,
+    AllReceivers,
+    GemsAndReceivers,
+    Gems( u32 )                             // at least this many gems, or every gem if the level has fewer
+}
+
+impl Default for WinCondition {
+    fn default() -> Self {
+        return WinCondition::AllGems;
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GemColor{
+    Red,
+    Green,
+    Yellow,
+    Purple
+}
+
+impl GemColor{
+    pub fn name( &self ) -> &'static str {
+        match self {
+            GemColor::Red => return "red",
+            GemColor::Green => return "green",
+            GemColor::Yellow => return "yellow",
+            GemColor::Purple => return "purple"
+        }
     }
+
+    pub fn from_name( name: &str ) -> Option<GemColor> {
+        return [ GemColor::Red, GemColor::Green, GemColor::Yellow, GemColor::Purple ].iter().copied().find( |color| color.name() == name );
+    }
+}
+
+// How far the lit gems follow the level's gem order
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GemOrder{
+    Pending,                                // no gem is out of order yet but some ordered gems are still dark
+    Kept,                                   // every gem of an ordered colour is lit and in order
+    Broken                                  // a gem was lit before a gem of a colour that comes earlier in the order
+}
+
+// Optional descriptive data stored with a level. Levels without it get the defaults
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LevelMetadata{
+    pub title: String,
+    pub author: String,
+    pub par_moves: Option<u32>,
+    pub difficulty: Option<u32>,
+    pub hints: Vec<String>,
+    pub edges: EdgeBehaviour,
+    pub teleport_exits: Vec<TeleportExit>,
+    pub win: WinCondition,
+    pub gem_order: Vec<GemColor>            // colours whose gems must be lit in this order. Other colours may be lit at any time
+}
+
+// A teleport that sends the beam out in a fixed direction instead of the direction it entered the partner teleport in
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TeleportExit{
+    pub x: u32,
+    pub y: u32,
+    pub direction: BeamDirection
 }
+
+// What has changed in a level during the current attempt. It is kept apart from the tile layers so undoing a move
+// or restarting the level can put it back
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct AttemptState{
+    pub spent_gems: Vec<(u32,u32)>          // one-shot gems that have been lit and now block beams, in the order they were lit
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SolutionStatus{
+    Solved,
+    Missing,
+    PiecesDiffer,                           // the solution layer does not hold the same movable pieces or rotatable mirrors as the front layer
+    Blocked( u32, u32 ),                    // a solution piece is placed on an occupied tile
+    GemsMissed{ hit: u32, total: u32 },
+    ReceiversMissed{ hit: u32, total: u32 },
+    BombLit( u32, u32 ),                    // the solved beams cross a bomb
+    GemOrderBroken                          // the solved beams light the gems out of the level's gem order
+}
+
 #[derive( Clone, Copy)]
 struct RayTransition{
     x: u32,
@@ -150,20 +485,33 @@ struct RayTransition{
     time_entered: f64,
 }
 
+#[derive(Clone)]
 pub struct Level{
     pub width:u32,
     pub height: u32,
+    has_solution: bool,
+    metadata: LevelMetadata,
     front: Vec<u8>,
     effect:Vec<TileEffect>,
     back: Vec<u8>,
     solution: Vec<u8>,
     ray: Vec<u8>,
+    ray_color: Vec<BeamColor>,
+    lit: Vec<bool>,                             // gems lit by the part of the beams shown in the ray layer
+    ray_looping: bool,                          // a beam goes round in a loop forever
+    ray_teleported: bool,                       // the shown beams reached a teleport for the first time on the last update
+    open_doors: Vec<u32>,                       // channels of the doors the beams hold open
+    lit_bombs: Vec<(u32,u32)>,                  // bombs crossed by the part of the beams shown in the ray layer
+    receivers_hit: u32,                         // receivers reached by the part of the beams shown in the ray layer
+    lit_gems: Vec<(u32,u32)>,                   // gems lit by the shown beams in the order they were reached
+    attempt: AttemptState,
     
     ray_transitions: Vec<RayTransition>
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
 #[derive(Clone, Copy)]
+#[serde(rename_all = "lowercase")]
 pub enum BeamDirection{
     Up,
     Left,
@@ -189,51 +537,259 @@ fn beam_direction_to_vec(direction: &BeamDirection) -> Vec2 {
 }
 
 impl Level{
-    pub fn load_level( number: u32 ) -> Level {
-        let mut path_buf = env::current_dir().unwrap();
-        path_buf = path_buf.join("levels/level".to_string() + &number.to_string() + &".mp".to_string());
-        let input = fs::read(path_buf).unwrap();
-    
-    
+    pub fn load_level( assets: &Assets, number: u32 ) -> Result<Level,LevelError> {
+        let name = "levels/level".to_string() + &number.to_string();
+        let input = match assets.read( &( name.clone() + ".mp" ) ).or_else( || assets.read( &( name.clone() + ".txt" ) ) ) {
+            Some( input ) => input,
+            None => return Err( LevelError::MissingFile( PathBuf::from( name + ".mp" ) ) )
+        };
+        return Level::from_data( &input );
+    }
+
+    // Read a level stored in either the binary .mp format or the text format
+    pub fn from_data( input: &[u8] ) -> Result<Level,LevelError> {
+        if level_text::is_text_level( input ) {
+            return level_text::level_from_text( &String::from_utf8_lossy( input ) );
+        }
+        return Level::from_bytes( input );
+    }
+
+    // Parse the binary .mp layout: version, width, height, has_solution followed by the back, front and solution layers.
+    // Version 1 files follow the layers with a 16 bit little endian length and that many bytes of JSON metadata
+    pub fn from_bytes( input: &[u8] ) -> Result<Level,LevelError> {
+        // has_solution is stored as 0 or 1. Anything else could not be written back the same
+        if input.len() < 4 || input[0] > 1 || input[1] == 0 || input[2] == 0 || input[3] > 1 {
+            return Err( LevelError::BadHeader );
+        }
+        let version = input[0];
         let width = input[1];
         let height = input[2];
-        let has_solution = input[3];
+        let has_solution = input[3] == 1;
         let layer_size = (width as u32 *height as u32 ) as usize;
+        let layers_end = layer_size*3+4;
+        let expected_length = if version == 0 || input.len() < layers_end+2 {
+            layers_end + if version == 0 { 0 } else { 2 }
+        } else {
+            layers_end + 2 + u16::from_le_bytes( [ input[ layers_end ], input[ layers_end+1 ] ] ) as usize
+        };
+        if input.len() != expected_length {
+            return Err( LevelError::WrongLength{ expected: expected_length, actual: input.len() } );
+        }
     
         let back: Vec<u8> = input[ 4..layer_size+4].to_vec();
         let front: Vec<u8> = input[ layer_size+4..layer_size*2+4].to_vec();
-        let solution: Vec<u8> = input[ (layer_size*2+4)..(layer_size*3+4)].into();
-        let effect: Vec<TileEffect> = vec![TileEffect::None;width as usize*height as usize];
-        return Level{ width: width as u32, height: height as u32, front, effect, back, solution, ray: vec![ Tile::EmptyPiece.into(); layer_size],
-            ray_transitions: Vec::new()  };
+        let solution: Vec<u8> = input[ (layer_size*2+4)..layers_end].into();
+        let mut level = Level::from_layers( width as u32, height as u32, has_solution, back, front, solution )?;
+        if version == 1 {
+            let metadata = serde_json::from_slice( &input[ layers_end+2.. ] ).map_err( |err| LevelError::BadMetadata( err.to_string() ) )?;
+            level.set_metadata( metadata );
+        }
+        level.check_teleports()?;
+        level.check_gem_order()?;
+        return Ok( level );
     }
 
-    pub fn tile_movable_effect ( &mut self, tile_effect: TileEffect ) {
+    // Every teleport channel in use must have exactly two teleports and every teleport exit must be on a teleport
+    pub fn check_teleports( &self ) -> Result<(),LevelError> {
+        let mut channels: HashMap<u32,usize> = HashMap::new();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if let Some( channel ) = self.front_tile( x, y ).teleport_channel() {
+                    *channels.entry( channel ).or_insert( 0 ) += 1;
+                }
+            }
+        }
+        let mut channel_counts: Vec<(u32,usize)> = channels.into_iter().collect();
+        channel_counts.sort();
+        if let Some( ( channel, count ) ) = channel_counts.into_iter().find( |( _, count )| *count != 2 ) {
+            return Err( LevelError::UnpairedTeleport{ channel, count } );
+        }
+        for exit in self.metadata.teleport_exits.iter() {
+            if exit.x >= self.width || exit.y >= self.height || !self.front_tile( exit.x, exit.y ).is_teleport() {
+                return Err( LevelError::BadTeleportExit( exit.x, exit.y ) );
+            }
+        }
+        return Ok( () );
+    }
+
+    pub fn check_gem_order( &self ) -> Result<(),LevelError> {
+        for ( idx, color ) in self.metadata.gem_order.iter().enumerate() {
+            if self.metadata.gem_order[ ..idx ].contains( color ) {
+                return Err( LevelError::BadGemOrder( *color ) );
+            }
+        }
+        return Ok( () );
+    }
+
+    // The direction a beam leaves the teleport at ( x, y ) in if the level fixes it
+    pub fn teleport_exit( &self, x: u32, y: u32 ) -> Option<BeamDirection> {
+        return self.metadata.teleport_exits.iter().find( |exit| exit.x == x && exit.y == y ).map( |exit| exit.direction );
+    }
+
+    // Build a level from its three tile layers. Every layer must hold width*height valid tiles
+    pub fn from_layers( width: u32, height: u32, has_solution: bool, back: Vec<u8>, front: Vec<u8>, solution: Vec<u8> ) -> Result<Level,LevelError> {
+        if width == 0 || height == 0 || width > 255 || height > 255 {
+            return Err( LevelError::BadHeader );
+        }
+        let layer_size = (width*height) as usize;
+        for ( layer_idx, layer ) in [ &back, &front, &solution ].iter().enumerate() {
+            if layer.len() != layer_size {
+                return Err( LevelError::WrongLength{ expected: layer_size*3+4, actual: back.len()+front.len()+solution.len()+4 } );
+            }
+            for ( idx, value ) in layer.iter().enumerate() {
+                if Tile::try_from( *value ).is_err() {
+                    return Err( LevelError::UnknownTile{ offset: 4 + layer_idx*layer_size + idx, value: *value } );
+                }
+            }
+        }
+        let effect: Vec<TileEffect> = vec![TileEffect::None;layer_size];
+        return Ok( Level{ width, height, has_solution, metadata: LevelMetadata::default(), front, effect, back, solution, ray: vec![ Tile::EmptyPiece.into(); layer_size],
+            ray_color: vec![ BeamColor::White; layer_size ], lit: vec![ false; layer_size ], ray_looping: false, ray_teleported: false, open_doors: Vec::new(), lit_bombs: Vec::new(), receivers_hit: 0, lit_gems: Vec::new(), attempt: AttemptState::default(), ray_transitions: Vec::new()  } );
+    }
+
+    // Produce the binary .mp layout read by from_bytes. Levels without metadata are written as version 0
+    pub fn to_bytes( &self ) -> Result<Vec<u8>,LevelError> {
+        let layer_size = (self.width*self.height) as usize;
+        let has_metadata = self.metadata != LevelMetadata::default();
+        let mut output: Vec<u8> = Vec::with_capacity( layer_size*3+4 );
+        output.push( if has_metadata { 1 } else { 0 } );
+        output.push( self.width as u8 );
+        output.push( self.height as u8 );
+        output.push( if self.has_solution { 1 } else { 0 } );
+        output.extend_from_slice( &self.back );
+        output.extend_from_slice( &self.front );
+        output.extend_from_slice( &self.solution );
+        if has_metadata {
+            let json = serde_json::to_vec( &self.metadata ).unwrap();
+            // The length is stored in 16 bits
+            if json.len() > u16::MAX as usize {
+                return Err( LevelError::BadMetadata( format!( "{} bytes of JSON, at most {} fit in a level", json.len(), u16::MAX ) ) );
+            }
+            output.extend_from_slice( &( json.len() as u16 ).to_le_bytes() );
+            output.extend_from_slice( &json );
+        }
+        return Ok( output );
+    }
+
+    pub fn save<P: AsRef<Path>>( &self, path: P ) -> io::Result<()> {
+        let bytes = self.to_bytes().map_err( |err| io::Error::new( io::ErrorKind::InvalidData, err.to_string() ) )?;
+        return fs::write( path, bytes );
+    }
+
+    // A level with no tiles. Used in place of a level that failed to load
+    pub fn empty() -> Level {
+        return Level{ width: 0, height: 0, has_solution: false, metadata: LevelMetadata::default(), front: Vec::new(), effect: Vec::new(), back: Vec::new(), solution: Vec::new(), ray: Vec::new(),
+            ray_color: Vec::new(), lit: Vec::new(), ray_looping: false, ray_teleported: false, open_doors: Vec::new(), lit_bombs: Vec::new(), receivers_hit: 0, lit_gems: Vec::new(), attempt: AttemptState::default(), ray_transitions: Vec::new() };
+    }
+
+    fn movable_pieces( layer: &Vec<u8> ) -> Vec<u8> {
+        let mut pieces: Vec<u8> = layer.iter().cloned().filter( |tile| Tile::try_from( *tile ).unwrap().is_movable() ).collect();
+        pieces.sort();
+        return pieces;
+    }
+
+    // Offsets of the rotatable mirrors in a layer
+    fn rotatable_pieces( layer: &Vec<u8> ) -> Vec<usize> {
+        return layer.iter().enumerate().filter( |( _, tile )| Tile::try_from( **tile ).unwrap().is_rotatable() ).map( |( offset, _ )| offset ).collect();
+    }
+
+    // Move the movable pieces to the positions stored in the solution layer and turn the rotatable
+    // mirrors to the stored orientation. Rotatable mirrors are stored in the place they stay in
+    pub fn apply_solution( &mut self ) -> Result<(),SolutionStatus> {
+        if !self.has_solution || ( Level::movable_pieces( &self.solution ).len() == 0 && Level::rotatable_pieces( &self.solution ).len() == 0 ) {
+            return Err( SolutionStatus::Missing );
+        }
+        if Level::movable_pieces( &self.solution ) != Level::movable_pieces( &self.front ) || Level::rotatable_pieces( &self.solution ) != Level::rotatable_pieces( &self.front ) {
+            return Err( SolutionStatus::PiecesDiffer );
+        }
         for y in 0..self.height {
             for x in 0..self.width {
                 if self.front_tile( x, y ).is_movable() {
-                    self.set_effect(x, y, tile_effect );
+                    self.set_front_tile( x, y, Tile::EmptyPiece );
                 }
             }
         }
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let tile = self.solution_tile( x, y );
+                if tile.is_rotatable() {
+                    self.set_front_tile( x, y, tile );
+                } else if tile.is_movable() {
+                    if self.front_tile( x, y ) != Tile::EmptyPiece {
+                        return Err( SolutionStatus::Blocked( x, y ) );
+                    }
+                    self.set_front_tile( x, y, tile );
+                }
+            }
+        }
+        return Ok( () );
+    }
+
+    // Apply the stored solution to a copy of the level and check that the ray then hits every gem. One-shot gems only
+    // count when the beams of the solution reach them
+    pub fn verify_solution( &self ) -> SolutionStatus {
+        let mut solved_level = self.clone();
+        if let Err( status ) = solved_level.apply_solution() {
+            return status;
+        }
+        // As in the game a layout that does not win straight away spends the one-shot gems its beams light, which
+        // then block the beams
+        let mut path = beam::trace_beam( &solved_level );
+        if path.bombs.is_empty() && !solved_level.is_won_by( &path ) && !solved_level.spend_gems( &path.gems ).is_empty() {
+            path = beam::trace_beam( &solved_level );
+        }
+        if let Some( ( x, y ) ) = path.bombs.first() {
+            return SolutionStatus::BombLit( *x, *y );
+        }
+        if solved_level.gem_order( &path.gems ) == GemOrder::Broken {
+            return SolutionStatus::GemOrderBroken;
+        }
+        if !solved_level.is_won_by( &path ) {
+            let needs_receivers = self.metadata.win == WinCondition::AllReceivers || self.metadata.win == WinCondition::GemsAndReceivers;
+            if needs_receivers && path.receiver_count() != solved_level.count_receivers() {
+                return SolutionStatus::ReceiversMissed{ hit: path.receiver_count(), total: solved_level.count_receivers() };
+            }
+            return SolutionStatus::GemsMissed{ hit: path.gem_count(), total: solved_level.count_jewels() };
+        }
+        return SolutionStatus::Solved;
     }
 
-    fn find_start( &self ) -> ( u32, u32, BeamDirection ) {
+    pub fn tile_movable_effect ( &mut self, tile_effect: TileEffect ) {
         for y in 0..self.height {
             for x in 0..self.width {
-                if self.front_tile( x, y ).is_ray_source() {
-                    let direction = match self.front_tile(x,y) {
-                        Tile::RaySourceUp => BeamDirection::Up,
-                        Tile::RaySourceDown => BeamDirection::Down,
-                        Tile::RaySourceLeft => BeamDirection::Left,
-                        Tile::RaySourceRight => BeamDirection::Right,
-                        _ => panic!( "unhandled tile direction")
-                    };
-                    return (x,y,direction);
+                if self.front_tile( x, y ).is_movable() {
+                    self.set_effect(x, y, tile_effect );
                 }
             }
         }
-        return (0,0,BeamDirection::Up);
+    }
+
+    // The one-shot gems in gems that are not spent yet become spent, in the order given. Returns the newly spent gems
+    pub fn spend_gems( &mut self, gems: &[(u32,u32)] ) -> Vec<(u32,u32)> {
+        let mut spent: Vec<(u32,u32)> = Vec::new();
+        for ( x, y ) in gems.iter() {
+            if self.front_tile( *x, *y ) == Tile::GemOneShot && !self.attempt.spent_gems.contains( &( *x, *y ) ) {
+                self.attempt.spent_gems.push( ( *x, *y ) );
+                spent.push( ( *x, *y ) );
+            }
+        }
+        return spent;
+    }
+
+    pub fn is_spent_gem( &self, x: u32, y: u32 ) -> bool {
+        return self.attempt.spent_gems.contains( &( x, y ) );
+    }
+
+    pub fn has_one_shot_gems( &self ) -> bool {
+        return self.front.iter().any( |tile| Tile::try_from( *tile ) == Ok( Tile::GemOneShot ) );
+    }
+
+    pub fn attempt( &self ) -> &AttemptState {
+        return &self.attempt;
+    }
+
+    pub fn set_attempt( &mut self, attempt: AttemptState ) {
+        self.attempt = attempt;
     }
 
     pub fn count_jewels( &self ) -> u32 {
@@ -248,159 +804,181 @@ impl Level{
         return gems;
     }
 
-    fn find_teleports( &self ) -> HashMap<Tile,Vec<(u32,u32)>> {
-        let mut teleport_pairs: HashMap<Tile,Vec<(u32,u32)>> = HashMap::new();
+    pub fn count_receivers( &self ) -> u32 {
+        let mut receivers = 0;
         for y in 0..self.height {
             for x in 0..self.width {
-                let tile = self.front_tile(x, y);
-                if tile.is_teleport() {
-                    teleport_pairs.entry(tile).or_insert_with(||vec![]).push((x,y));
+                if self.front_tile( x, y ).receiver_entry().is_some() {
+                    receivers += 1;
                 }
             }
         }
-        return teleport_pairs;
+        return receivers;
     }
 
-    fn move_ray( &self, x: &mut u32, y: &mut u32, direction: &BeamDirection  ) {
-        match direction {
-            BeamDirection::Up => {
-                if *y == 0 {
-                    *y = self.height;
-                }
-                *y -= 1;
-            },
-            BeamDirection::Down => {
-                *y += 1;
-                if *y == self.height {
-                    *y = 0;
+    // Check the gems lit, in the order they were lit, against the level's gem order
+    pub fn gem_order( &self, gems_lit: &[(u32,u32)] ) -> GemOrder {
+        let rank = |x: u32, y: u32| self.front_tile( x, y ).gem_color().and_then( |color| self.metadata.gem_order.iter().position( |ordered| *ordered == color ) );
+        let mut highest = 0;
+        for ( x, y ) in gems_lit.iter() {
+            if let Some( rank ) = rank( *x, *y ) {
+                if rank < highest {
+                    return GemOrder::Broken;
                 }
-            },
-            BeamDirection::Left => {
-                if *x == 0 {
-                    *x = self.width;
-                }
-                *x -= 1;
-            },
-            BeamDirection::Right => {
-                *x += 1;
-                if *x == self.width {
-                    *x = 0;
+                highest = rank;
+            }
+        }
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if rank( x, y ).is_some() && !gems_lit.contains( &( x, y ) ) {
+                    return GemOrder::Pending;
                 }
-            },
+            }
         }
+        return GemOrder::Kept;
     }
 
-    //  Return the number of jewels the ray crosses
+    // The one place the win condition is checked. The game passes what the beams on screen reach and the
+    // solver and level tools what the traced beams reach. Gems are passed in the order they were lit
+    pub fn is_won( &self, gems_lit: &[(u32,u32)], receivers_hit: u32, bomb_lit: bool ) -> bool {
+        if bomb_lit || self.gem_order( gems_lit ) == GemOrder::Broken {
+            return false;
+        }
+        let gems_lit = gems_lit.len() as u32;
+        let all_gems = gems_lit == self.count_jewels();
+        let all_receivers = receivers_hit == self.count_receivers();
+        match self.metadata.win {
+            WinCondition::AllGems => return all_gems,
+            WinCondition::AllReceivers => return all_receivers,
+            WinCondition::GemsAndReceivers => return all_gems && all_receivers,
+            WinCondition::Gems( count ) => return gems_lit >= count.min( self.count_jewels() )
+        }
+    }
+
+    pub fn is_won_by( &self, path: &BeamPath ) -> bool {
+        return self.is_won( &path.gems, path.receiver_count(), path.bombs.len() > 0 );
+    }
+
+    // Draw the beams into the ray layer. Only the first max_length tiles from each source are shown and a beam
+    // pauses briefly at each turn, teleport and split it has not reached before. Beams that loop are drawn once round
+    // the loop. Returns the number of jewels the shown beams cross
     pub fn update_ray( &mut self, max_length: usize, time_in_page: f64) -> u32 {
-        let last_ray = self.ray.clone();
-        for idx in 0..self.width*self.height {
-            self.ray[ idx as usize ] = 0;
+        let path = beam::trace_beam( self );
+        self.ray_looping = path.is_looping();
+        self.open_doors = path.open_doors.clone();
+        self.lit_bombs.clear();
+        self.receivers_hit = 0;
+        self.lit_gems.clear();
+        self.ray_teleported = false;
+        let last_lit = self.lit.clone();
+        for idx in 0..self.ray.len() {
+            self.ray[ idx ] = 0;
+            self.ray_color[ idx ] = BeamColor::White;
+            self.lit[ idx ] = false;
         }
-        let ( mut beam_x, mut beam_y, mut direction ) = self.find_start();        
+        // Spent one-shot gems stay lit for the rest of the attempt and were lit before anything the beams reach now
         let mut jewel_count = 0;
-        let teleports = self.find_teleports();
+        for ( x, y ) in self.attempt.spent_gems.clone() {
+            let offset = self.offset( x, y );
+            self.lit[ offset ] = true;
+            self.lit_gems.push( ( x, y ) );
+            jewel_count += 1;
+        }
         let mut new_transitions: Vec<RayTransition> = Vec::new();
-
-        // Move the ray out of its source ( its normally a blocker )
-        self.move_ray( &mut beam_x, &mut beam_y, &direction );
-        for _count in 0..max_length {
-            // Check current pos
-            let tile =  self.front_tile(beam_x, beam_y);
-            let mut show_ray = true;
-            if tile.is_ray_blocker(direction) {
-                break;
-            } else if tile.is_a_gem() {
-                // If there is also a ray on the tile we cant count it as we have been here before
-                if self.ray_tile( beam_x, beam_y ) == Tile::EmptyPiece {
-                    jewel_count += 1;
-                    if last_ray[ self.offset(beam_x,beam_y) ] == 0 {
-                        let offset = self.offset(beam_x,beam_y);
-                        self.effect[ offset ] = TileEffect::Punch( time_in_page as f32, beam_direction_to_vec(&direction)*40.0 );
-                    }
-                }
-            }
-            else if tile.is_ray_source() {
-                show_ray = false;
-                break;
-
-            } else if tile == Tile::EmptyPiece {
-
-            } else if tile == Tile::MovableTopLeft || tile == Tile::ImmovableTopLeft{
-                show_ray = false;
-                match direction {
-                    BeamDirection::Up => { direction = BeamDirection::Right},
-                    BeamDirection::Left => { direction = BeamDirection::Down},
-                    _ => { break }
-                }
-            } else if tile == Tile::MovableTopRight || tile == Tile::ImmovableTopRight{
-                show_ray = false;
-                match direction {
-                    BeamDirection::Right => { direction = BeamDirection::Down},
-                    BeamDirection::Up => { direction = BeamDirection::Left},
-                    _ => { break }
-                }
-            } else if tile == Tile::MovableBottomLeft || tile == Tile::ImmovableBottomLeft{
-                show_ray = false;
-                match direction {
-                    BeamDirection::Down => { direction = BeamDirection::Right},
-                    BeamDirection::Left => { direction = BeamDirection::Up},
-                    _ => { break }
-                }
-            } else if tile == Tile::MovableBottomRight || tile == Tile::ImmovableBottomRight{
-                show_ray = false;
-                match direction {
-                    BeamDirection::Down => { direction = BeamDirection::Left},
-                    BeamDirection::Right => { direction = BeamDirection::Up},
-                    _ => { break }
-                }   
-            }
-            else if teleports.contains_key(&tile) {
-                let pair = teleports.get(&tile).unwrap();
-                show_ray = false;
-                if beam_x == pair[ 0 ].0 && beam_y == pair[ 0 ].1 {
-                    beam_x = pair[ 1 ].0;
-                    beam_y = pair[ 1 ].1;
-                } else {
-                    beam_x = pair[ 0 ].0;
-                    beam_y = pair[ 0 ].1;
-                }
-            }
-
-            if show_ray {
-                if self.ray_tile( beam_x, beam_y ) != Tile::EmptyPiece {
-                    self.set_ray_tile(beam_x, beam_y, Tile::RayCross);
-                } else {
-                    match direction {
-                        BeamDirection::Up | BeamDirection::Down => self.set_ray_tile(beam_x, beam_y, Tile::RayVertical),
-                        BeamDirection::Left | BeamDirection::Right => self.set_ray_tile(beam_x, beam_y, Tile::RayHorizontal)
-                    }
+        // Steps shown of each beam and how far from its source each beam starts
+        let mut shown: Vec<usize> = Vec::new();
+        let mut starts: Vec<usize> = Vec::new();
+        for ( beam_idx, beam ) in path.beams.iter().enumerate() {
+            let start = beam.parent.map_or( 0, |( parent, step )| starts[ parent ] + step + 1 );
+            starts.push( start );
+            shown.push( 0 );
+            // A split beam only appears once the beam before it has got through the splitter
+            if let Some( ( parent, step ) ) = beam.parent {
+                if shown[ parent ] <= step {
+                    continue;
                 }
             }
-            // The ray briefly pauses after each turn or teleport. If we are not showing the ray, this is is one of those points
-            if !show_ray { 
-                let mut transition_index = None;
-                for ( index, transition) in self.ray_transitions.iter().enumerate() {
-                    if transition.x == beam_x && transition.y == beam_y {
-                        transition_index = Some( index );
-                    }
-                }
-                match transition_index {
-                    None => {
-                        // we have not encountered this before. Add it to the list
-                        new_transitions.push( RayTransition{ x: beam_x, y: beam_y, time_entered: time_in_page } );
+            // A beam crossing its own path always shows a cross unless it is coming straight back off a reflecting
+            // edge. Where different beams overlap in the same direction the ray is left as it is
+            let mut drawn: HashMap<usize,BeamDirection> = HashMap::new();
+            for step in beam.steps.iter().take( max_length.saturating_sub( start ) ) {
+                let ( beam_x, beam_y ) = ( step.x, step.y );
+                let offset = self.offset( beam_x, beam_y );
+                match step.event {
+                    BeamEvent::End => {
+                        // Receivers are lit like gems so they glow
+                        if let BeamEnd::Received( _, _ ) = beam.end {
+                            if !self.lit[ offset ] {
+                                self.receivers_hit += 1;
+                                self.lit[ offset ] = true;
+                            }
+                        }
                         break;
                     },
-                    Some( idx ) =>  { 
-                        new_transitions.push( *self.ray_transitions.get(idx).unwrap() );
-                        if ( time_in_page - new_transitions.last().unwrap().time_entered ) < 0.075 {
-                            break;
+                    BeamEvent::Pass => {
+                        if self.front_tile( beam_x, beam_y ) == Tile::Bomb && !self.lit_bombs.contains( &( beam_x, beam_y ) ) {
+                            self.lit_bombs.push( ( beam_x, beam_y ) );
+                        }
+                        // A gem only counts the first time a beam of its colour reaches it
+                        if beam.color.lights( self.front_tile( beam_x, beam_y ) ) && !self.lit[ offset ] {
+                            jewel_count += 1;
+                            self.lit[ offset ] = true;
+                            if !last_lit[ offset ] {
+                                self.effect[ offset ] = TileEffect::Punch( time_in_page as f32, beam_direction_to_vec( &step.direction )*40.0 );
+                            }
+                        }
+                        let ray_tile = match step.direction {
+                            BeamDirection::Up | BeamDirection::Down => Tile::RayVertical,
+                            BeamDirection::Left | BeamDirection::Right => Tile::RayHorizontal
+                        };
+                        let current = self.ray_tile( beam_x, beam_y );
+                        if current == Tile::EmptyPiece {
+                            self.set_ray_tile( beam_x, beam_y, ray_tile );
+                            self.ray_color[ offset ] = beam.color;
+                        } else {
+                            let crossed = drawn.get( &offset ).map_or( false, |earlier| *earlier != beam::reverse( step.direction ) );
+                            if crossed || current != ray_tile {
+                                self.set_ray_tile( beam_x, beam_y, Tile::RayCross );
+                            }
+                            // Where beams of different colours meet the ray is drawn white
+                            if self.ray_color[ offset ] != beam.color {
+                                self.ray_color[ offset ] = BeamColor::White;
+                            }
+                        }
+                        drawn.insert( offset, step.direction );
+                    },
+                    BeamEvent::Turn( _ ) | BeamEvent::Teleport( _, _ ) | BeamEvent::Split => {
+                        // The ray briefly pauses after each turn, teleport or split
+                        let ( pause_x, pause_y ) = match step.event {
+                            BeamEvent::Teleport( to_x, to_y ) => ( to_x, to_y ),
+                            _ => ( beam_x, beam_y )
+                        };
+                        match self.ray_transitions.iter().rev().find( |transition| transition.x == pause_x && transition.y == pause_y ) {
+                            None => {
+                                // we have not encountered this before. Add it to the list
+                                new_transitions.push( RayTransition{ x: pause_x, y: pause_y, time_entered: time_in_page } );
+                                if let BeamEvent::Teleport( _, _ ) = step.event {
+                                    self.ray_teleported = true;
+                                }
+                                break;
+                            },
+                            Some( transition ) => {
+                                new_transitions.push( *transition );
+                                if ( time_in_page - transition.time_entered ) < 0.075 {
+                                    break;
+                                }
+                            }
                         }
                     }
-
                 }
+                shown[ beam_idx ] += 1;
+            }
+        }
+        // Gems are listed in the order the beams reach them rather than the order the beams were drawn in
+        for ( x, y ) in beam::gems_in_order( self, &path.beams ) {
+            if self.lit[ self.offset( x, y ) ] && !self.is_spent_gem( x, y ) {
+                self.lit_gems.push( ( x, y ) );
             }
-
-            self.move_ray( &mut beam_x, &mut beam_y, &direction );
         }
         self.ray_transitions = new_transitions;
         return jewel_count;
@@ -413,9 +991,6 @@ impl Level{
     pub fn back_tile_idx( &self, x: u32,y: u32 ) -> u8 {
         return self.back[ self.offset(x,y) ];
     }
-    pub fn front_tile_idx( &self, x: u32,y: u32 ) -> u8 {
-        return self.front[ self.offset(x,y) ];
-    }
     pub fn ray_tile_idx( &self, x: u32,y: u32 ) -> u8 {
         return self.ray[ self.offset(x,y) ];
     }
@@ -435,6 +1010,22 @@ impl Level{
         self.effect[ offset ] = tile_effect;
     }
 
+    pub fn solution_tile( &self, x: u32,y: u32 ) -> Tile {
+        return Tile::try_from( self.solution[ self.offset(x,y) ] ).unwrap();
+    }
+
+    pub fn has_solution( &self ) -> bool {
+        return self.has_solution;
+    }
+
+    pub fn metadata( &self ) -> &LevelMetadata {
+        return &self.metadata;
+    }
+
+    pub fn set_metadata( &mut self, metadata: LevelMetadata ) {
+        self.metadata = metadata;
+    }
+
     pub fn back_tile( &self, x: u32,y: u32 ) -> Tile {
         let tile =  Tile::try_from( self.back[ self.offset(x,y) ] ).unwrap();
         return tile;
@@ -444,14 +1035,133 @@ impl Level{
         return Tile::try_from( self.ray[ self.offset(x,y) ] ).unwrap();
     }
 
+    pub fn ray_color( &self, x: u32,y: u32 ) -> BeamColor {
+        return self.ray_color[ self.offset(x,y) ];
+    }
+
+    // True if the last update_ray showed a beam jumping through a teleport it had not reached before
+    pub fn ray_teleported( &self ) -> bool {
+        return self.ray_teleported;
+    }
+
+    // Receivers reached by the beams shown by the last update_ray
+    pub fn receivers_hit( &self ) -> u32 {
+        return self.receivers_hit;
+    }
+
+    // Gems lit by the beams shown by the last update_ray in the order the beams reached them
+    pub fn lit_gems( &self ) -> &Vec<(u32,u32)> {
+        return &self.lit_gems;
+    }
+
+    // Bombs crossed by the beams shown by the last update_ray
+    pub fn lit_bombs( &self ) -> &Vec<(u32,u32)> {
+        return &self.lit_bombs;
+    }
+
+    // Channels of the doors held open by the beams traced by the last update_ray
+    pub fn open_doors( &self ) -> &Vec<u32> {
+        return &self.open_doors;
+    }
+
+    // True if a beam traced by the last update_ray goes round in a loop
+    pub fn is_ray_looping( &self ) -> bool {
+        return self.ray_looping;
+    }
+
+    pub fn is_lit( &self, x: u32,y: u32 ) -> bool {
+        return self.lit[ self.offset(x,y) ];
+    }
+
+    // Movable pieces can only be slid onto empty tiles that have a floor under them
+    pub fn is_open_tile( &self, x: u32,y: u32 ) -> bool {
+        return self.front_tile( x, y ) == Tile::EmptyPiece && self.back_tile( x, y ) != Tile::EmptyPiece;
+    }
+
+    // Pieces can also be slid across open doors but cant be left on them. A drag uses the doors that were open when
+    // the piece was picked up, so moving the piece does not open or close doors in its way
+    pub fn is_passable_tile( &self, x: u32,y: u32, open_doors: &[u32] ) -> bool {
+        let open_door = self.front_tile( x, y ).door_channel().map_or( false, |channel| open_doors.contains( &channel ) );
+        return self.is_open_tile( x, y ) || ( open_door && self.back_tile( x, y ) != Tile::EmptyPiece );
+    }
+
     pub fn set_front_tile( &mut self, x: u32,y: u32, tile: Tile ) {
         let offset = self.offset(x,y); 
         self.front[ offset ] = tile.into();
     }
 
+    pub fn set_solution_tile( &mut self, x: u32,y: u32, tile: Tile ) {
+        let offset = self.offset(x,y); 
+        self.solution[ offset ] = tile.into();
+    }
+
     pub fn set_ray_tile( &mut self, x: u32,y: u32, tile: Tile ) {
         let offset = self.offset(x,y); 
         self.ray[ offset ] = tile.into();
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+    use super::{Level, SolutionStatus};
+    use super::super::level_text;
+
+    #[test]
+    fn shipped_levels_round_trip() {
+        let dir = Path::new( env!( "CARGO_MANIFEST_DIR" ) ).join( "levels" );
+        for entry in fs::read_dir( &dir ).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().map_or( true, |ext| ext != "mp" ) {
+                continue;
+            }
+            let input = fs::read( &path ).unwrap();
+            let level = Level::from_bytes( &input ).unwrap_or_else( |err| panic!( "{:?}: {}", path, err ) );
+            assert_eq!( level.to_bytes().unwrap(), input, "{:?}", path );
+        }
+    }
+
+    #[test]
+    fn has_solution_must_be_0_or_1() {
+        let mut input = vec![ 0, 1, 1, 2 ];
+        input.extend_from_slice( &[ 0; 3 ] );
+        assert!( Level::from_bytes( &input ).is_err() );
+        input[ 3 ] = 1;
+        assert!( Level::from_bytes( &input ).is_ok() );
+    }
+
+    #[test]
+    fn unlit_one_shot_gems_are_not_solved() {
+        let text = "beam_puzzle level 0\nhas_solution 1\nedges absorb\nback\n22222\n22222\n22222\nfront\n>...G\ny....\na....\nsolution\n.....\n.....\na....\n";
+        let level = level_text::level_from_text( text ).unwrap();
+        assert_eq!( level.verify_solution(), SolutionStatus::GemsMissed{ hit: 1, total: 2 } );
+    }
+
+    #[test]
+    fn lone_teleports_do_not_load() {
+        let text = "beam_puzzle level 0\nhas_solution 0\nback\n222\nfront\n>.%\nsolution\n...\n";
+        assert!( level_text::level_from_text( text ).is_err() );
+        assert!( level_text::level_from_text( &text.replace( ">.%", ">%%" ) ).is_ok() );
+        assert!( level_text::level_from_text( &text.replace( ">.%", "%%%" ) ).is_err() );
+    }
+
+    #[test]
+    fn empty_metadata_values_parse() {
+        let text = "beam_puzzle level 0\nhas_solution 0\ntitle \nhint\nback\n2\nfront\n.\nsolution\n.\n";
+        let level = level_text::level_from_text( text ).unwrap();
+        assert_eq!( level.metadata().title, "" );
+        assert_eq!( level.metadata().hints, vec![ String::new() ] );
+        let bytes = level.to_bytes().unwrap();
+        assert_eq!( level_text::mp_to_text( &bytes ).unwrap(), level_text::level_to_text( &level ) );
+    }
+
+    #[test]
+    fn oversized_metadata_is_an_error() {
+        let mut level = Level::from_bytes( &[ 0, 1, 1, 0, 0, 0, 0 ] ).unwrap();
+        let mut metadata = level.metadata().clone();
+        metadata.title = "x".repeat( u16::MAX as usize );
+        level.set_metadata( metadata );
+        assert!( level.to_bytes().is_err() );
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use super::level::{Level, LevelError};
+use super::assets::Assets;
+
+// A level pack holds an ordered set of levels in a single file
+//
+//  "BPPK"                      magic
+//  u8                          container version ( 0 )
+//  u32 + JSON                  manifest
+//  repeated:
+//      u16 + utf8              entry name
+//      u32 + level data        .mp bytes or the text format
+//
+// All lengths are little endian. The manifest decides the play order by naming the entries
+const PACK_MAGIC: &[u8] = b"BPPK";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PackManifest{
+    pub name: String,
+    pub version: u32,
+    pub levels: Vec<String>
+}
+
+pub struct LevelPack{
+    manifest: PackManifest,
+    entries: HashMap<String,Vec<u8>>
+}
+
+fn pack_error( message: &str ) -> LevelError {
+    return LevelError::BadPack( message.to_string() );
+}
+
+struct PackReader<'a>{
+    input: &'a [u8],
+    pos: usize
+}
+
+impl <'a>PackReader<'a>{
+    fn bytes( &mut self, count: usize ) -> Result<&'a [u8],LevelError> {
+        if self.pos + count > self.input.len() {
+            return Err( pack_error( "unexpected end of pack" ) );
+        }
+        let bytes = &self.input[ self.pos..self.pos+count ];
+        self.pos += count;
+        return Ok( bytes );
+    }
+
+    fn u16_block( &mut self ) -> Result<&'a [u8],LevelError> {
+        let len = self.bytes( 2 )?;
+        let len = u16::from_le_bytes( [ len[0], len[1] ] ) as usize;
+        return self.bytes( len );
+    }
+
+    fn u32_block( &mut self ) -> Result<&'a [u8],LevelError> {
+        let len = self.bytes( 4 )?;
+        let len = u32::from_le_bytes( [ len[0], len[1], len[2], len[3] ] ) as usize;
+        return self.bytes( len );
+    }
+}
+
+impl LevelPack{
+    pub fn new( manifest: PackManifest, entries: HashMap<String,Vec<u8>> ) -> Result<LevelPack,LevelError> {
+        for name in manifest.levels.iter() {
+            if !entries.contains_key( name ) {
+                return Err( pack_error( &format!( "manifest lists missing level {}", name ) ) );
+            }
+        }
+        return Ok( LevelPack{ manifest, entries } );
+    }
+
+    // Build a pack from the level1, level2, .. files of a level directory. The numbering decides the order
+    pub fn from_directory( dir: &Path, name: &str ) -> Result<LevelPack,LevelError> {
+        return LevelPack::from_numbered_levels( name, &|file_name| fs::read( dir.join( file_name ) ).ok() )
+            .ok_or_else( || LevelError::MissingFile( dir.join( "level1.mp" ) ) );
+    }
+
+    fn from_numbered_levels( name: &str, read: &dyn Fn( &str ) -> Option<Vec<u8>> ) -> Option<LevelPack> {
+        let mut levels: Vec<String> = Vec::new();
+        let mut entries: HashMap<String,Vec<u8>> = HashMap::new();
+        for number in 1.. {
+            let level_name = "level".to_string() + &number.to_string();
+            let data = match read( &( level_name.clone() + ".mp" ) ).or_else( || read( &( level_name.clone() + ".txt" ) ) ) {
+                Some( data ) => data,
+                None => break
+            };
+            levels.push( level_name.clone() );
+            entries.insert( level_name, data );
+        }
+        if levels.len() == 0 {
+            return None;
+        }
+        return Some( LevelPack{ manifest: PackManifest{ name: name.to_string(), version: 1, levels }, entries } );
+    }
+
+    pub fn from_bytes( input: &[u8] ) -> Result<LevelPack,LevelError> {
+        if !input.starts_with( PACK_MAGIC ) || input.len() < PACK_MAGIC.len()+1 || input[ PACK_MAGIC.len() ] != 0 {
+            return Err( LevelError::BadHeader );
+        }
+        let mut reader = PackReader{ input, pos: PACK_MAGIC.len()+1 };
+        let manifest: PackManifest = serde_json::from_slice( reader.u32_block()? ).map_err( |err| pack_error( &err.to_string() ) )?;
+        let mut entries: HashMap<String,Vec<u8>> = HashMap::new();
+        while reader.pos < input.len() {
+            let name = String::from_utf8( reader.u16_block()?.to_vec() ).map_err( |_| pack_error( "entry name is not utf8" ) )?;
+            let data = reader.u32_block()?.to_vec();
+            entries.insert( name, data );
+        }
+        return LevelPack::new( manifest, entries );
+    }
+
+    pub fn to_bytes( &self ) -> Vec<u8> {
+        let mut output: Vec<u8> = PACK_MAGIC.to_vec();
+        output.push( 0 );
+        let json = serde_json::to_vec( &self.manifest ).unwrap();
+        output.extend_from_slice( &( json.len() as u32 ).to_le_bytes() );
+        output.extend_from_slice( &json );
+        for name in self.manifest.levels.iter() {
+            let data = &self.entries[ name ];
+            output.extend_from_slice( &( name.len() as u16 ).to_le_bytes() );
+            output.extend_from_slice( name.as_bytes() );
+            output.extend_from_slice( &( data.len() as u32 ).to_le_bytes() );
+            output.extend_from_slice( data );
+        }
+        return output;
+    }
+
+    pub fn manifest( &self ) -> &PackManifest {
+        return &self.manifest;
+    }
+
+    pub fn len( &self ) -> u32 {
+        return self.manifest.levels.len() as u32;
+    }
+
+    // Levels are numbered from 1 like the level files and the saved progress
+    pub fn level( &self, number: u32 ) -> Result<Level,LevelError> {
+        if number == 0 || number > self.len() {
+            return Err( pack_error( &format!( "pack has no level {}", number ) ) );
+        }
+        let name = &self.manifest.levels[ number as usize - 1 ];
+        return Level::from_data( &self.entries[ name ] );
+    }
+}
+
+// The campaign played by the game. A levels/campaign.pack asset takes priority over the individual level files
+pub fn load_default_pack( assets: &Assets ) -> Result<LevelPack,LevelError> {
+    if let Some( input ) = assets.read( "levels/campaign.pack" ) {
+        return LevelPack::from_bytes( &input );
+    }
+    return LevelPack::from_numbered_levels( "Beam Puzzle", &|file_name| assets.read( &( "levels/".to_string() + file_name ) ) )
+        .ok_or_else( || LevelError::MissingFile( PathBuf::from( "levels/level1.mp" ) ) );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::path::Path;
+    use super::{LevelPack, PackManifest};
+
+    #[test]
+    fn packs_round_trip_through_bytes() {
+        let dir = Path::new( env!( "CARGO_MANIFEST_DIR" ) ).join( "levels" );
+        let pack = LevelPack::from_directory( &dir, "Test" ).unwrap();
+        assert!( pack.len() > 0 );
+        let loaded = LevelPack::from_bytes( &pack.to_bytes() ).unwrap();
+        assert_eq!( loaded.manifest().name, "Test" );
+        assert_eq!( loaded.manifest().levels, pack.manifest().levels );
+        for number in 1..=pack.len() {
+            assert_eq!( loaded.level( number ).unwrap().to_bytes().unwrap(), pack.level( number ).unwrap().to_bytes().unwrap() );
+        }
+        assert!( loaded.level( 0 ).is_err() );
+        assert!( loaded.level( pack.len()+1 ).is_err() );
+    }
+
+    #[test]
+    fn broken_packs_are_rejected() {
+        let dir = Path::new( env!( "CARGO_MANIFEST_DIR" ) ).join( "levels" );
+        let bytes = LevelPack::from_directory( &dir, "Test" ).unwrap().to_bytes();
+        assert!( LevelPack::from_bytes( &bytes[ ..bytes.len()-1 ] ).is_err() );
+        assert!( LevelPack::from_bytes( &bytes[ 1.. ] ).is_err() );
+        let manifest = PackManifest{ name: "Test".to_string(), version: 1, levels: vec![ "level1".to_string() ] };
+        assert!( LevelPack::new( manifest, HashMap::new() ).is_err() );
+    }
+}
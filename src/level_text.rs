@@ -0,0 +1,470 @@
+use super::level::{BeamDirection, EdgeBehaviour, GemColor, Level, LevelError, LevelMetadata, TeleportExit, Tile, WinCondition};
+
+// Text version of the .mp format so levels can be read and diffed by people.
+//
+//  beam_puzzle level 0
+//  has_solution 1
+//  title <text>            optional metadata lines
+//  author <text>
+//  par <moves>
+//  difficulty <number>
+//  hint <text>             may be repeated
+//  edges <behaviour>       wrap ( the default ), absorb or reflect
+//  exit <x> <y> <dir>      the teleport at x, y sends beams up, right, down or left. May be repeated
+//  win <condition>         all_gems ( the default ), all_receivers, gems_and_receivers or gems <count>
+//  order <colours>         gems of these colours must be lit in this order, e.g. order red green yellow
+//  back
+//  <height rows of width characters>
+//  front
+//  <rows>
+//  solution
+//  <rows>
+//
+// Every tile is one character. Blank lines are ignored. Coloured sources run up, right, down, left:
+// red efgh, green ijkl, yellow mnop, purple suwx. Splitters are z ( movable ) and Z. Rotatable mirrors
+// are E, F, I and K for top left, top right, bottom left and bottom right. Teleport channels 1 to 6 are % & $ * { }.
+// Switches S and V open the doors N and M. Bombs are O. Receivers open at the top, right, bottom and left are Q U W X.
+// One-shot gems that turn into walls once lit are y. Movable blockers are : and movable pass filters _ and ;
+// ( horizontal and vertical )
+pub const TEXT_HEADER: &str = "beam_puzzle level";
+
+pub fn tile_to_char( tile: Tile ) -> char {
+    match tile {
+        Tile::EmptyPiece => '.',
+        Tile::WallTerminatorTop => '\'',
+        Tile::WallTerminatorBottom => ',',
+        Tile::WallTerminatorLeft => '(',
+        Tile::WallTerminatorRight => ')',
+        Tile::WallHorizontal => '-',
+        Tile::WallVertical => '|',
+        Tile::WallTurnTopLeft => 'r',
+        Tile::WallTurnTopRight => 'q',
+        Tile::WallTurnBottomLeft => 'L',
+        Tile::WallTurnBottomRight => 'J',
+        Tile::WallTLeft => '[',
+        Tile::WallTRight => ']',
+        Tile::WallTUp => 't',
+        Tile::WallTDown => 'T',
+        Tile::WallBlocker => '#',
+        Tile::Solid => '@',
+        Tile::PassHorizontal => '=',
+        Tile::PassVertical => 'H',
+        Tile::RayVertical => '!',
+        Tile::RayHorizontal => '~',
+        Tile::RayCross => '+',
+        Tile::RayTeleport1 => '%',
+        Tile::RayTeleport2 => '&',
+        Tile::RayTeleport3 => '$',
+        Tile::RayTeleport4 => '*',
+        Tile::RayTeleport5 => '{',
+        Tile::RayTeleport6 => '}',
+        Tile::SwitchA => 'S',
+        Tile::SwitchB => 'V',
+        Tile::DoorA => 'N',
+        Tile::DoorB => 'M',
+        Tile::Bomb => 'O',
+        Tile::ReceiverTop => 'Q',
+        Tile::ReceiverRight => 'U',
+        Tile::ReceiverBottom => 'W',
+        Tile::ReceiverLeft => 'X',
+        Tile::GemOneShot => 'y',
+        Tile::MovableBlocker => ':',
+        Tile::MovablePassHorizontal => '_',
+        Tile::MovablePassVertical => ';',
+        Tile::RaySourceUp => '^',
+        Tile::RaySourceRight => '>',
+        Tile::RaySourceDown => 'v',
+        Tile::RaySourceLeft => '<',
+        Tile::MovableTopLeft => 'a',
+        Tile::MovableTopRight => 'b',
+        Tile::MovableBottomLeft => 'c',
+        Tile::MovableBottomRight => 'd',
+        Tile::ImmovableTopLeft => 'A',
+        Tile::ImmovableTopRight => 'B',
+        Tile::ImmovableBottomLeft => 'C',
+        Tile::ImmovableBottomRight => 'D',
+        Tile::GemRed => 'R',
+        Tile::GemGreen => 'G',
+        Tile::GemYellow => 'Y',
+        Tile::GemPurple => 'P',
+        Tile::Floor1 => '1',
+        Tile::Floor2 => '2',
+        Tile::Floor3 => '3',
+        Tile::Floor4 => '4',
+        Tile::Floor5 => '5',
+        Tile::Floor6 => '6',
+        Tile::Floor7 => '7',
+        Tile::RaySourceRedUp => 'e',
+        Tile::RaySourceRedRight => 'f',
+        Tile::RaySourceRedDown => 'g',
+        Tile::RaySourceRedLeft => 'h',
+        Tile::RaySourceGreenUp => 'i',
+        Tile::RaySourceGreenRight => 'j',
+        Tile::RaySourceGreenDown => 'k',
+        Tile::RaySourceGreenLeft => 'l',
+        Tile::RaySourceYellowUp => 'm',
+        Tile::RaySourceYellowRight => 'n',
+        Tile::RaySourceYellowDown => 'o',
+        Tile::RaySourceYellowLeft => 'p',
+        Tile::RaySourcePurpleUp => 's',
+        Tile::RaySourcePurpleRight => 'u',
+        Tile::RaySourcePurpleDown => 'w',
+        Tile::RaySourcePurpleLeft => 'x',
+        Tile::MovableSplitter => 'z',
+        Tile::ImmovableSplitter => 'Z',
+        Tile::RotatableTopLeft => 'E',
+        Tile::RotatableTopRight => 'F',
+        Tile::RotatableBottomLeft => 'I',
+        Tile::RotatableBottomRight => 'K',
+    }
+}
+
+pub fn char_to_tile( c: char ) -> Option<Tile> {
+    let tile = match c {
+        '.' => Tile::EmptyPiece,
+        '\'' => Tile::WallTerminatorTop,
+        ',' => Tile::WallTerminatorBottom,
+        '(' => Tile::WallTerminatorLeft,
+        ')' => Tile::WallTerminatorRight,
+        '-' => Tile::WallHorizontal,
+        '|' => Tile::WallVertical,
+        'r' => Tile::WallTurnTopLeft,
+        'q' => Tile::WallTurnTopRight,
+        'L' => Tile::WallTurnBottomLeft,
+        'J' => Tile::WallTurnBottomRight,
+        '[' => Tile::WallTLeft,
+        ']' => Tile::WallTRight,
+        't' => Tile::WallTUp,
+        'T' => Tile::WallTDown,
+        '#' => Tile::WallBlocker,
+        '@' => Tile::Solid,
+        '=' => Tile::PassHorizontal,
+        'H' => Tile::PassVertical,
+        '!' => Tile::RayVertical,
+        '~' => Tile::RayHorizontal,
+        '+' => Tile::RayCross,
+        '%' => Tile::RayTeleport1,
+        '&' => Tile::RayTeleport2,
+        '$' => Tile::RayTeleport3,
+        '*' => Tile::RayTeleport4,
+        '{' => Tile::RayTeleport5,
+        '}' => Tile::RayTeleport6,
+        'S' => Tile::SwitchA,
+        'V' => Tile::SwitchB,
+        'N' => Tile::DoorA,
+        'M' => Tile::DoorB,
+        'O' => Tile::Bomb,
+        'Q' => Tile::ReceiverTop,
+        'U' => Tile::ReceiverRight,
+        'W' => Tile::ReceiverBottom,
+        'X' => Tile::ReceiverLeft,
+        'y' => Tile::GemOneShot,
+        ':' => Tile::MovableBlocker,
+        '_' => Tile::MovablePassHorizontal,
+        ';' => Tile::MovablePassVertical,
+        '^' => Tile::RaySourceUp,
+        '>' => Tile::RaySourceRight,
+        'v' => Tile::RaySourceDown,
+        '<' => Tile::RaySourceLeft,
+        'a' => Tile::MovableTopLeft,
+        'b' => Tile::MovableTopRight,
+        'c' => Tile::MovableBottomLeft,
+        'd' => Tile::MovableBottomRight,
+        'A' => Tile::ImmovableTopLeft,
+        'B' => Tile::ImmovableTopRight,
+        'C' => Tile::ImmovableBottomLeft,
+        'D' => Tile::ImmovableBottomRight,
+        'R' => Tile::GemRed,
+        'G' => Tile::GemGreen,
+        'Y' => Tile::GemYellow,
+        'P' => Tile::GemPurple,
+        '1' => Tile::Floor1,
+        '2' => Tile::Floor2,
+        '3' => Tile::Floor3,
+        '4' => Tile::Floor4,
+        '5' => Tile::Floor5,
+        '6' => Tile::Floor6,
+        '7' => Tile::Floor7,
+        'e' => Tile::RaySourceRedUp,
+        'f' => Tile::RaySourceRedRight,
+        'g' => Tile::RaySourceRedDown,
+        'h' => Tile::RaySourceRedLeft,
+        'i' => Tile::RaySourceGreenUp,
+        'j' => Tile::RaySourceGreenRight,
+        'k' => Tile::RaySourceGreenDown,
+        'l' => Tile::RaySourceGreenLeft,
+        'm' => Tile::RaySourceYellowUp,
+        'n' => Tile::RaySourceYellowRight,
+        'o' => Tile::RaySourceYellowDown,
+        'p' => Tile::RaySourceYellowLeft,
+        's' => Tile::RaySourcePurpleUp,
+        'u' => Tile::RaySourcePurpleRight,
+        'w' => Tile::RaySourcePurpleDown,
+        'x' => Tile::RaySourcePurpleLeft,
+        'z' => Tile::MovableSplitter,
+        'Z' => Tile::ImmovableSplitter,
+        'E' => Tile::RotatableTopLeft,
+        'F' => Tile::RotatableTopRight,
+        'I' => Tile::RotatableBottomLeft,
+        'K' => Tile::RotatableBottomRight,
+        _ => return None
+    };
+    return Some( tile );
+}
+
+pub fn is_text_level( input: &[u8] ) -> bool {
+    return input.starts_with( TEXT_HEADER.as_bytes() );
+}
+
+pub fn level_to_text( level: &Level ) -> String {
+    let mut text = String::new();
+    text.push_str( TEXT_HEADER );
+    text.push_str( " 0\n" );
+    text.push_str( &format!( "has_solution {}\n", if level.has_solution() { 1 } else { 0 } ) );
+    let metadata = level.metadata();
+    if !metadata.title.is_empty() {
+        text.push_str( &format!( "title {}\n", metadata.title ) );
+    }
+    if !metadata.author.is_empty() {
+        text.push_str( &format!( "author {}\n", metadata.author ) );
+    }
+    if let Some( par_moves ) = metadata.par_moves {
+        text.push_str( &format!( "par {}\n", par_moves ) );
+    }
+    if let Some( difficulty ) = metadata.difficulty {
+        text.push_str( &format!( "difficulty {}\n", difficulty ) );
+    }
+    for hint in metadata.hints.iter() {
+        text.push_str( &format!( "hint {}\n", hint ) );
+    }
+    match metadata.edges {
+        EdgeBehaviour::Wrap => {},
+        EdgeBehaviour::Absorb => text.push_str( "edges absorb\n" ),
+        EdgeBehaviour::Reflect => text.push_str( "edges reflect\n" )
+    }
+    for exit in metadata.teleport_exits.iter() {
+        text.push_str( &format!( "exit {} {} {}\n", exit.x, exit.y, direction_name( exit.direction ) ) );
+    }
+    match metadata.win {
+        WinCondition::AllGems => {},
+        WinCondition::AllReceivers => text.push_str( "win all_receivers\n" ),
+        WinCondition::GemsAndReceivers => text.push_str( "win gems_and_receivers\n" ),
+        WinCondition::Gems( count ) => text.push_str( &format!( "win gems {}\n", count ) )
+    }
+    if !metadata.gem_order.is_empty() {
+        let colors: Vec<&str> = metadata.gem_order.iter().map( |color| color.name() ).collect();
+        text.push_str( &format!( "order {}\n", colors.join( " " ) ) );
+    }
+
+    let layers: [ ( &str, &dyn Fn( u32, u32 ) -> Tile ); 3 ] = [
+        ( "back", &|x,y| level.back_tile( x, y ) ),
+        ( "front", &|x,y| level.front_tile( x, y ) ),
+        ( "solution", &|x,y| level.solution_tile( x, y ) ) ];
+    for ( name, layer_tile ) in layers.iter() {
+        text.push_str( name );
+        text.push( '\n' );
+        for y in 0..level.height {
+            for x in 0..level.width {
+                text.push( tile_to_char( layer_tile( x, y ) ) );
+            }
+            text.push( '\n' );
+        }
+    }
+    return text;
+}
+
+fn direction_name( direction: BeamDirection ) -> &'static str {
+    match direction {
+        BeamDirection::Up => return "up",
+        BeamDirection::Right => return "right",
+        BeamDirection::Down => return "down",
+        BeamDirection::Left => return "left"
+    }
+}
+
+fn parse_exit( value: &str ) -> Option<TeleportExit> {
+    let parts: Vec<&str> = value.split_whitespace().collect();
+    if parts.len() != 3 {
+        return None;
+    }
+    let direction = [ BeamDirection::Up, BeamDirection::Right, BeamDirection::Down, BeamDirection::Left ].iter()
+        .find( |direction| direction_name( **direction ) == parts[ 2 ] )?;
+    return Some( TeleportExit{ x: parts[ 0 ].parse().ok()?, y: parts[ 1 ].parse().ok()?, direction: *direction } );
+}
+
+fn parse_error( line: usize, message: &str ) -> LevelError {
+    return LevelError::Parse{ line, message: message.to_string() };
+}
+
+pub fn level_from_text( text: &str ) -> Result<Level,LevelError> {
+    let mut lines = text.lines().enumerate().map( |(idx, line)| (idx+1, line.trim_end() ) ).filter( |(_, line)| !line.is_empty() ).peekable();
+
+    match lines.next() {
+        Some( ( _, line ) ) if line == TEXT_HEADER.to_string() + " 0" => {},
+        _ => return Err( LevelError::BadHeader )
+    }
+    let has_solution = match lines.next() {
+        Some( ( _, "has_solution 0" ) ) => false,
+        Some( ( _, "has_solution 1" ) ) => true,
+        Some( ( line_no, _ ) ) => return Err( parse_error( line_no, "expected has_solution 0 or 1" ) ),
+        None => return Err( LevelError::BadHeader )
+    };
+
+    let mut metadata = LevelMetadata::default();
+    while let Some( ( line_no, line ) ) = lines.peek() {
+        let line_no = *line_no;
+        // Trailing spaces are trimmed so a key on its own has an empty value
+        let ( key, value ) = match line.find( ' ' ) {
+            Some( idx ) => ( &line[ ..idx ], line[ idx+1.. ].to_string() ),
+            None => ( *line, String::new() )
+        };
+        if key == "back" {
+            break;
+        }
+        match key {
+            "title" => metadata.title = value,
+            "author" => metadata.author = value,
+            "par" => metadata.par_moves = Some( value.parse().map_err( |_| parse_error( line_no, "par must be a number" ) )? ),
+            "difficulty" => metadata.difficulty = Some( value.parse().map_err( |_| parse_error( line_no, "difficulty must be a number" ) )? ),
+            "hint" => metadata.hints.push( value ),
+            "exit" => metadata.teleport_exits.push( parse_exit( &value ).ok_or_else( || parse_error( line_no, "exit must be x y and up, right, down or left" ) )? ),
+            "win" => metadata.win = match value.as_str() {
+                "all_gems" => WinCondition::AllGems,
+                "all_receivers" => WinCondition::AllReceivers,
+                "gems_and_receivers" => WinCondition::GemsAndReceivers,
+                _ => match value.strip_prefix( "gems " ).and_then( |count| count.parse().ok() ) {
+                    Some( count ) => WinCondition::Gems( count ),
+                    None => return Err( parse_error( line_no, "win must be all_gems, all_receivers, gems_and_receivers or gems <count>" ) )
+                }
+            },
+            "order" => metadata.gem_order = value.split_whitespace().map( GemColor::from_name ).collect::<Option<Vec<GemColor>>>()
+                .ok_or_else( || parse_error( line_no, "order must list red, green, yellow or purple" ) )?,
+            "edges" => metadata.edges = match value.as_str() {
+                "wrap" => EdgeBehaviour::Wrap,
+                "absorb" => EdgeBehaviour::Absorb,
+                "reflect" => EdgeBehaviour::Reflect,
+                _ => return Err( parse_error( line_no, "edges must be wrap, absorb or reflect" ) )
+            },
+            _ => return Err( parse_error( line_no, &format!( "unknown metadata key {}", key ) ) )
+        }
+        lines.next();
+    }
+
+    let mut width: Option<usize> = None;
+    let mut layers: Vec<Vec<u8>> = Vec::new();
+    for name in [ "back", "front", "solution" ].iter() {
+        match lines.next() {
+            Some( ( _, line ) ) if line == *name => {},
+            Some( ( line_no, _ ) ) => return Err( parse_error( line_no, &format!( "expected {} layer", name ) ) ),
+            None => return Err( parse_error( 0, &format!( "missing {} layer", name ) ) )
+        }
+        let mut layer: Vec<u8> = Vec::new();
+        let mut rows = 0;
+        while let Some( ( line_no, line ) ) = lines.peek() {
+            if *line == "front" || *line == "solution" {
+                break;
+            }
+            let line_no = *line_no;
+            let row_width = line.chars().count();
+            if *width.get_or_insert( row_width ) != row_width {
+                return Err( parse_error( line_no, "row width does not match the rest of the level" ) );
+            }
+            for c in line.chars() {
+                match char_to_tile( c ) {
+                    Some( tile ) => layer.push( tile.into() ),
+                    None => return Err( parse_error( line_no, &format!( "unknown tile character '{}'", c ) ) )
+                }
+            }
+            rows += 1;
+            lines.next();
+        }
+        if rows == 0 {
+            return Err( parse_error( 0, &format!( "{} layer is empty", name ) ) );
+        }
+        if layers.len() > 0 && layer.len() != layers[ 0 ].len() {
+            return Err( parse_error( 0, &format!( "{} layer size does not match the back layer", name ) ) );
+        }
+        layers.push( layer );
+    }
+    if let Some( ( line_no, _ ) ) = lines.next() {
+        return Err( parse_error( line_no, "unexpected data after the solution layer" ) );
+    }
+
+    let width = width.unwrap() as u32;
+    let height = layers[ 0 ].len() as u32 / width;
+    let solution = layers.pop().unwrap();
+    let front = layers.pop().unwrap();
+    let back = layers.pop().unwrap();
+    let mut level = Level::from_layers( width, height, has_solution, back, front, solution )?;
+    level.set_metadata( metadata );
+    level.check_teleports()?;
+    level.check_gem_order()?;
+    return Ok( level );
+}
+
+// Byte level conversion used by the level tools. Both directions are checked to be lossless
+pub fn mp_to_text( input: &[u8] ) -> Result<String,LevelError> {
+    let level = Level::from_bytes( input )?;
+    let text = level_to_text( &level );
+    if level_from_text( &text )?.to_bytes()? != input {
+        return Err( parse_error( 0, "text conversion is not lossless" ) );
+    }
+    return Ok( text );
+}
+
+pub fn text_to_mp( text: &str ) -> Result<Vec<u8>,LevelError> {
+    let level = level_from_text( text )?;
+    let bytes = level.to_bytes()?;
+    if level_to_text( &Level::from_bytes( &bytes )? ) != level_to_text( &level ) {
+        return Err( parse_error( 0, "binary conversion is not lossless" ) );
+    }
+    return Ok( bytes );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+    use super::{char_to_tile, level_from_text, level_to_text, mp_to_text, text_to_mp, tile_to_char};
+
+    #[test]
+    fn tile_characters_round_trip() {
+        for c in ( 0u8..128 ).map( |c| c as char ) {
+            if let Some( tile ) = char_to_tile( c ) {
+                assert_eq!( tile_to_char( tile ), c );
+            }
+        }
+    }
+
+    #[test]
+    fn shipped_levels_round_trip_through_text() {
+        let dir = Path::new( env!( "CARGO_MANIFEST_DIR" ) ).join( "levels" );
+        let mut count = 0;
+        for entry in fs::read_dir( &dir ).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().map_or( true, |ext| ext != "mp" ) {
+                continue;
+            }
+            let data = fs::read( &path ).unwrap();
+            let text = mp_to_text( &data ).unwrap_or_else( |err| panic!( "{:?}: {}", path, err ) );
+            assert_eq!( text_to_mp( &text ).unwrap(), data, "{:?}", path );
+            count += 1;
+        }
+        assert!( count > 0 );
+    }
+
+    #[test]
+    fn metadata_round_trips_through_text() {
+        let text = "beam_puzzle level 0\nhas_solution 1\ntitle Two ways\nauthor Someone\npar 2\ndifficulty 3\nhint Look up\nedges reflect\nexit 1 0 down\nwin gems 1\norder red green\nback\n222\n222\nfront\n>%R\n.%G\nsolution\n...\n...\n";
+        let level = level_from_text( text ).unwrap();
+        assert_eq!( level.metadata().title, "Two ways" );
+        assert_eq!( level_to_text( &level ), text );
+    }
+
+    #[test]
+    fn bad_text_is_rejected() {
+        assert!( level_from_text( "beam_puzzle level 0\nhas_solution 0\nback\n22\nfront\n>?\nsolution\n..\n" ).is_err() );
+        assert!( level_from_text( "beam_puzzle level 0\nhas_solution 0\nedges bounce\nback\n22\nfront\n>.\nsolution\n..\n" ).is_err() );
+        assert!( level_from_text( "beam_puzzle level 0\nhas_solution 0\nback\n222\nfront\n>.\nsolution\n...\n" ).is_err() );
+    }
+}
@@ -7,11 +7,19 @@ mod tile_batcher;
 mod page;
 mod page_manager;
 mod level;
+mod level_text;
+mod level_pack;
 mod ui;
 mod render_level;
 mod audio;
 mod config;
 mod editable_constants;
+mod tools;
+mod assets;
+mod solver;
+mod beam;
+mod generator;
+mod difficulty;
 
 use glium_glyph::glyph_brush::{rusttype::Font};
 use glium_glyph::GlyphBrush;
@@ -42,6 +50,12 @@ glium::implement_vertex!(Vertex, position, tex_coords,color);        // don't fo
 
 
 fn main() {
+    let args: Vec<String> = env::args().skip( 1 ).collect();
+    if let Some( exit_code ) = tools::run_tool( &args ) {
+        std::process::exit( exit_code );
+    }
+
+    let assets = assets::Assets::from_args( &args );
     let config = config::Config::new( );
 
     let event_loop = glutin::event_loop::EventLoop::new();
@@ -49,16 +63,14 @@ fn main() {
     let mut wb = glutin::window::WindowBuilder::new().with_inner_size(glutin::dpi::LogicalSize::new(config.width(), config.height())).with_drag_and_drop(false);
     let cb = glutin::ContextBuilder::new().with_depth_buffer(24).with_srgb(false).with_pixel_format(8, 8).with_vsync(true);
     let display = glium::Display::new(wb, cb, &event_loop).unwrap();
-    let audio = audio::Audio::new(&env::current_dir().unwrap());
+    let audio = audio::Audio::new(&assets);
 
 
 //    let image = image::load(Cursor::new(&include_bytes!("assets/walls2.png")[..]), image::ImageFormat::Png).unwrap().to_rgba8();
 //    let image = image::load(Cursor::new(&include_bytes!("assets/opengl.png")[..]), image::ImageFormat::Png).unwrap().to_rgba8();
 
-    let mut path_buf = env::current_dir().unwrap();
-    path_buf = path_buf.join("walls2.png");
-
-    let decoder = png::Decoder::new(std::fs::File::open(path_buf.as_path()).unwrap());
+    let atlas = assets.read("walls2.png").unwrap();
+    let decoder = png::Decoder::new(std::io::Cursor::new(atlas));
     let (col_info, mut reader) = decoder.read_info().unwrap();
     println!( "Texture image: {:?}", col_info);
     let mut tex_map = vec![0; col_info.buffer_size()];
@@ -74,9 +86,9 @@ fn main() {
 //    let texture = glium::texture::srgb_texture2d::SrgbTexture2d::new( &display, image).unwrap();
     let texture = glium::texture::srgb_texture2d::SrgbTexture2d::new( &display, image).unwrap();
 
-    let game_page = Box::new( game::GamePage::new(&config));
-    let main_menu_page = Box::new( main_menu::MainMenuPage::new());
-    let settings_page = Box::new( settings::SettingsPage::new());
+    let game_page = Box::new( game::GamePage::new(&config, &assets));
+    let main_menu_page = Box::new( main_menu::MainMenuPage::new(&assets));
+    let settings_page = Box::new( settings::SettingsPage::new(&assets));
 
     let mut pages: HashMap<page_manager::PageName, Box<dyn Page>> = HashMap::new();
     pages.insert(page_manager::PageName::Game, game_page);
@@ -108,8 +120,8 @@ fn main() {
                     page_manager.mouse_move(last_mouse_pos, &display);
                 },
                 glutin::event::WindowEvent::MouseInput{ device_id: _, state, button, modifiers: _ } => {
-                    if button == MouseButton::Left {
-                        page_manager.mouse_click(state == ElementState::Pressed, last_mouse_pos.clone(),&display);
+                    if button == MouseButton::Left || button == MouseButton::Right {
+                        page_manager.mouse_click(button, state == ElementState::Pressed, last_mouse_pos.clone(),&display);
                     }
                 },
                 glutin::event::WindowEvent::KeyboardInput{ device_id: _, input, is_synthetic } =>{
@@ -1,7 +1,7 @@
 use crate::{Vertex, page_manager};
 use glium_glyph::GlyphBrush;
 
-use super::glutin::event::VirtualKeyCode;
+use super::glutin::event::{MouseButton, VirtualKeyCode};
 use super::glium::Surface;
 use super::Vec2;
 use super::Vec4;
@@ -13,6 +13,7 @@ use super::page_manager::{PageAction, PageName};
 use super::ui::{button,MouseState};
 use super::render_level;
 use super::audio;
+use super::assets::Assets;
 use super::level::Tile;
 
 #[derive(Debug, PartialEq)]
@@ -27,8 +28,11 @@ pub struct MainMenuPage{
 }
 
 impl MainMenuPage{
-    pub fn new( ) -> MainMenuPage {
-        let level = Level::load_level(0);
+    pub fn new( assets: &Assets ) -> MainMenuPage {
+        let level = Level::load_level(assets, 0).unwrap_or_else( |err| {
+            println!( "Failed to load menu background level: {}", err );
+            Level::empty()
+        });
         return MainMenuPage{ level, game_state: MainMenuState::Showing};
     }
     
@@ -112,7 +116,7 @@ impl page::Page for MainMenuPage {
         target.finish().unwrap();   
     }
 
-    fn mouse_click( &mut self, pressed: bool, pos: Vec2 ) -> PageAction {
+    fn mouse_click( &mut self, button: MouseButton, pressed: bool, pos: Vec2 ) -> PageAction {
         return PageAction::None;
     }
 
@@ -6,7 +6,7 @@ use glium_glyph::glyph_brush::{rusttype::Font};
 use super::GlyphBrush;
 use super::GlyphBrushBuilder;
 
-use super::glutin::event::VirtualKeyCode;
+use super::glutin::event::{MouseButton, VirtualKeyCode};
 use super::{config, tile_batcher::TileBatcher};
 use super::Page;
 use super::{Vec2,Vec3, Vec4, Mat4};
@@ -176,10 +176,13 @@ impl <'a>PageManager<'a>{
         self.handle_page_action( action, display );
     }
 
-    pub fn mouse_click( &mut self, pressed: bool, pos: Vec2, display: &glium::Display ) {
+    pub fn mouse_click( &mut self, button: MouseButton, pressed: bool, pos: Vec2, display: &glium::Display ) {
         let logical_pos =  get_logical_pos( display, &pos );
-        let action = self.pages.get_mut( &self.current_page ).unwrap().mouse_click( pressed, logical_pos.clone_owned() );
-        self.mouse_state.button_state = if pressed { ButtonState::PressedDown } else { ButtonState::ReleasedUp };
+        let action = self.pages.get_mut( &self.current_page ).unwrap().mouse_click( button, pressed, logical_pos.clone_owned() );
+        // Only the left button presses ui buttons
+        if button == MouseButton::Left {
+            self.mouse_state.button_state = if pressed { ButtonState::PressedDown } else { ButtonState::ReleasedUp };
+        }
         self.mouse_state.pos = logical_pos;
         self.handle_page_action( action, display );
     }
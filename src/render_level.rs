@@ -2,7 +2,7 @@ use crate::level::apply_tile_effect;
 
 use super::Level;
 use super::tile_batcher::*;
-use super::{Vertex, level::Tile, level::tile_effect_to_offset};
+use super::{Vertex, level::Tile, level::tile_effect_to_offset, level::BeamDirection, level::EdgeBehaviour};
 use super::{Vec2,Vec4};
 use super::config::Config;
 use super::glm;
@@ -34,11 +34,26 @@ pub fn render_level(config: &Config, tile_batcher: &TileBatcher,  level: &Level,
 
     for y in 0..level.height{
         for x in 0..level.width {
-            let tile = level.front_tile_idx(x,y);
+            // Spent one-shot gems are drawn as blockers in the colour of the gem
+            let tile = if level.is_spent_gem(x,y) { Tile::WallBlocker.into() } else { level.front_tile(x,y).atlas_index() };
             let src: Vec2 = Vec2::new( (tile%14) as f32 / 16.0f32+qtr_pixel, 1.0-((tile/14) as f32 /16f32)-qtr_pixel );
             let dest: Vec2 = Vec2::new( x as f32*64.0, config.height() as f32 - y as f32*64.0 );
             let (final_pos, final_size, final_alpha ) : (Vec2,Vec2,f32 )=  apply_tile_effect(&level.effect(x,y), time_in_level as f32, &dest, &Vec2::new( 64.0, 64.0 ), 1.0f32 );
-            tile_batcher.tile_color(vertices,&final_pos, &final_size, &src, &Vec2::new( 1.0/16.0-half_pixel, 1.0/16.0-half_pixel ), &Vec4::new( 1f32, 1f32, 1f32, final_alpha ));
+            let ( r, g, b ) = level.front_tile(x,y).tint();
+            tile_batcher.tile_color(vertices,&final_pos, &final_size, &src, &Vec2::new( 1.0/16.0-half_pixel, 1.0/16.0-half_pixel ), &Vec4::new( r, g, b, final_alpha ));
+
+            // A small source arrow on a teleport shows the fixed direction beams leave it in
+            if let Some( direction ) = level.teleport_exit(x,y) {
+                let arrow: u8 = match direction {
+                    BeamDirection::Up => Tile::RaySourceUp.into(),
+                    BeamDirection::Right => Tile::RaySourceRight.into(),
+                    BeamDirection::Down => Tile::RaySourceDown.into(),
+                    BeamDirection::Left => Tile::RaySourceLeft.into()
+                };
+                let src: Vec2 = Vec2::new( (arrow%14) as f32 / 16.0f32+qtr_pixel, 1.0-((arrow/14) as f32 /16f32)-qtr_pixel );
+                let ( arrow_pos, arrow_size ) = scale( &final_pos, &final_size, 0.5 );
+                tile_batcher.tile_color(vertices,&arrow_pos, &arrow_size, &src, &Vec2::new( 1.0/16.0-half_pixel, 1.0/16.0-half_pixel ), &Vec4::new( 1.0, 1.0, 1.0, final_alpha*0.8 ));
+            }
         }
     }
 }
@@ -50,11 +65,11 @@ pub fn render_level_glows(config: &Config, tile_batcher: &TileBatcher,  level: &
     let ms_offset = 1f32 + (SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs_f64()*200f64).sin() as f32 * 0.05f32;
     for y in 0..level.height{
         for x in 0..level.width {
-            if level.front_tile(x,y).is_a_gem() {
-                let tile = level.front_tile_idx(x,y);
+            if ( level.front_tile(x,y).is_a_gem() && !level.is_spent_gem(x,y) ) || level.front_tile(x,y).receiver_entry().is_some() {
+                let tile = level.front_tile(x,y).atlas_index();
                 let src = Vec2::new( (tile%14) as f32 / 16.0f32+qtr_pixel, 1.0-((tile/14) as f32 /16f32)-qtr_pixel );
 
-                let strength = if level.ray_tile_idx(x,y) == 0 { 4 } else { 12 };
+                let strength = if level.is_lit(x,y) { 12 } else { 4 };
                 let mut scale_factor= ms_offset;
                 let offset: Vec2 = tile_effect_to_offset( &level.effect(x,y), time_in_level as f32 );
                 let dest: Vec2 = Vec2::new( x as f32*64.0, config.height() as f32 - y as f32*64.0 ) + offset;
@@ -86,13 +101,40 @@ pub fn render_level_glows(config: &Config, tile_batcher: &TileBatcher,  level: &
                 let ( scaled_pos, scaled_size ) = 
                     scale(&Vec2::new( x as f32*64.0, config.height() as f32 - y as f32*64.0 ), &Vec2::new( 64.0, 64.0 ), 1f32 );// scale_factor);
 
+                let ( r, g, b ) = level.ray_color(x,y).rgb();
                 tile_batcher.tile_color(vertices,&scaled_pos, &scaled_size, &src, &Vec2::new( 1.0/16.0-half_pixel, 1.0/16.0-half_pixel ), 
-                     &Vec4::new( 1.0, 1.0, 1.0, 0.5f32+scale_factor ));
+                     &Vec4::new( r, g, b, 0.5f32+scale_factor ));
 
                 scale_factor *= 1.05;
     
             }
         }
     }
- 
+
+    // On levels where beams wrap, faint rays across the open edge tiles show where a beam can leave and come back
+    if level.metadata().edges == EdgeBehaviour::Wrap && level.width > 0 && level.height > 0 {
+        let mut edge_tiles: Vec<(u32,u32,Tile)> = Vec::new();
+        for x in 0..level.width {
+            if !level.front_tile( x, 0 ).is_ray_blocker( BeamDirection::Up ) {
+                edge_tiles.push( ( x, 0, Tile::RayVertical ) );
+            }
+            if !level.front_tile( x, level.height-1 ).is_ray_blocker( BeamDirection::Down ) {
+                edge_tiles.push( ( x, level.height-1, Tile::RayVertical ) );
+            }
+        }
+        for y in 0..level.height {
+            if !level.front_tile( 0, y ).is_ray_blocker( BeamDirection::Left ) {
+                edge_tiles.push( ( 0, y, Tile::RayHorizontal ) );
+            }
+            if !level.front_tile( level.width-1, y ).is_ray_blocker( BeamDirection::Right ) {
+                edge_tiles.push( ( level.width-1, y, Tile::RayHorizontal ) );
+            }
+        }
+        for ( x, y, edge_tile ) in edge_tiles {
+            let tile: u8 = edge_tile.into();
+            let src = Vec2::new( (tile%14) as f32 / 16.0f32+qtr_pixel, 1.0-((tile/14) as f32 /16f32)-qtr_pixel );
+            tile_batcher.tile_color(vertices,&Vec2::new( x as f32*64.0, config.height() as f32 - y as f32*64.0 ), &Vec2::new( 64.0, 64.0 ), &src, &Vec2::new( 1.0/16.0-half_pixel, 1.0/16.0-half_pixel ), 
+                 &Vec4::new( 0.6, 0.8, 1.0, 0.15f32+ms_offset ));
+        }
+    }
 }
\ No newline at end of file
@@ -1,7 +1,7 @@
 use crate::{Vertex};
 use glium_glyph::GlyphBrush;
 
-use super::glutin::event::VirtualKeyCode;
+use super::glutin::event::{MouseButton, VirtualKeyCode};
 use super::glium::Surface;
 use super::Vec2;
 use super::Level;
@@ -13,14 +13,18 @@ use super::page_manager;
 use super::ui::{button,multi_selector,MouseState};
 use super::render_level;
 use super::audio;
+use super::assets::Assets;
 
 pub struct SettingsPage{
     level: Level,
 }
 
 impl SettingsPage{
-    pub fn new( ) -> SettingsPage {
-        let level = Level::load_level(0);
+    pub fn new( assets: &Assets ) -> SettingsPage {
+        let level = Level::load_level(assets, 0).unwrap_or_else( |err| {
+            println!( "Failed to load menu background level: {}", err );
+            Level::empty()
+        });
         return SettingsPage{ level };
     }
     
@@ -84,7 +88,7 @@ impl page::Page for SettingsPage {
         target.finish().unwrap();   
 
     }
-    fn mouse_click( &mut self, pressed: bool, pos: Vec2 ) -> PageAction {
+    fn mouse_click( &mut self, button: MouseButton, pressed: bool, pos: Vec2 ) -> PageAction {
         return PageAction::None;
     }
 
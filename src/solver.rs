@@ -0,0 +1,556 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use super::level::{AttemptState, Level, SolutionStatus, Tile};
+use super::beam;
+
+// One drag of a movable piece. A drag can slide the piece any distance over open tiles. A click on a rotatable
+// mirror is also a move: it stays where it is so from and to are the same and tile is the new orientation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Move{
+    pub tile: Tile,
+    pub from: (u32,u32),
+    pub to: (u32,u32)
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SolveResult{
+    Solved( Vec<Move> ),
+    Found( Vec<Move> ),         // a solution that is not proven to be the shortest
+    Unsolvable,
+    GaveUp( usize )             // number of states searched before hitting the limit
+}
+
+// Movable pieces and rotatable mirrors as ( tile offset, tile ) sorted by offset. Pieces of the same kind are interchangeable
+pub type PieceState = Vec<(usize,Tile)>;
+
+pub const MAX_PLACEMENT_NODES: usize = 1_000_000;
+
+// Tiles the player can change and so are part of the solver state
+fn is_piece( tile: Tile ) -> bool {
+    return tile.is_movable() || tile.is_rotatable();
+}
+
+// Layouts the player can reach from the start that can no longer be solved, out of the layouts looked at
+pub struct DeadEnds{
+    pub dead_ends: usize,
+    pub explored: usize
+}
+
+// Searches the placements of the movable pieces and the orientations of the rotatable mirrors. Everything else
+// in the level stays fixed apart from one-shot gems, which the search tracks as they are spent
+pub struct Solver{
+    level: Level,
+    pieces: Vec<Tile>,
+    rotatables: Vec<usize>,
+    one_shot: bool,                         // the level has one-shot gems so the same layout can win or lose depending on the moves before it
+    doors: bool,                            // the level has doors, which pieces can cross while they are open
+    bombs: bool,                            // the level has bombs, which fail the level when lit
+    stored: Option<PieceState>,             // the pieces of the stored solution if it solves the level
+    placements: Option<Vec<PieceState>>,
+    placements_complete: bool
+}
+
+impl Solver{
+    pub fn new( level: &Level ) -> Solver {
+        let stored = match level.verify_solution() {
+            SolutionStatus::Solved => {
+                let mut solved = level.clone();
+                solved.apply_solution().unwrap();
+                Some( Solver::initial_state( &solved ) )
+            },
+            _ => None
+        };
+        let mut level = level.clone();
+        let mut pieces: Vec<Tile> = Vec::new();
+        let mut rotatables: Vec<usize> = Vec::new();
+        for y in 0..level.height {
+            for x in 0..level.width {
+                let tile = level.front_tile( x, y );
+                if tile.is_movable() {
+                    pieces.push( tile );
+                } else if tile.is_rotatable() {
+                    rotatables.push( ( y*level.width+x ) as usize );
+                }
+                if is_piece( tile ) {
+                    level.set_front_tile( x, y, Tile::EmptyPiece );
+                }
+            }
+        }
+        let one_shot = level.has_one_shot_gems();
+        let doors = ( 0..level.height ).any( |y| ( 0..level.width ).any( |x| level.front_tile( x, y ).door_channel().is_some() ) );
+        let bombs = ( 0..level.height ).any( |y| ( 0..level.width ).any( |x| level.front_tile( x, y ) == Tile::Bomb ) );
+        return Solver{ level, pieces, rotatables, one_shot, doors, bombs, stored, placements: None, placements_complete: false };
+    }
+
+    pub fn initial_state( level: &Level ) -> PieceState {
+        let mut state: PieceState = Vec::new();
+        for y in 0..level.height {
+            for x in 0..level.width {
+                let tile = level.front_tile( x, y );
+                if is_piece( tile ) {
+                    state.push( ( ( y*level.width+x ) as usize, tile ) );
+                }
+            }
+        }
+        return state;
+    }
+
+    pub fn position( &self, offset: usize ) -> (u32,u32) {
+        return ( offset as u32 % self.level.width, offset as u32 / self.level.width );
+    }
+
+    fn place( &mut self, state: &PieceState, place: bool ) {
+        for ( offset, tile ) in state.iter() {
+            let ( x, y ) = self.position( *offset );
+            self.level.set_front_tile( x, y, if place { *tile } else { Tile::EmptyPiece } );
+        }
+    }
+
+    // True if the beams win the level with the pieces in the given places
+    pub fn is_solved( &mut self, state: &PieceState ) -> bool {
+        return self.is_solved_in( state, &AttemptState::default() );
+    }
+
+    fn is_solved_in( &mut self, state: &PieceState, attempt: &AttemptState ) -> bool {
+        self.place( state, true );
+        self.level.set_attempt( attempt.clone() );
+        let won = self.level.is_won_by( &beam::trace_beam( &self.level ) );
+        self.level.set_attempt( AttemptState::default() );
+        self.place( state, false );
+        return won;
+    }
+
+    // True if the beams light a bomb with the pieces in the given places. The level then fails so no move can follow
+    fn fails_in( &mut self, state: &PieceState, attempt: &AttemptState ) -> bool {
+        if !self.bombs {
+            return false;
+        }
+        self.place( state, true );
+        self.level.set_attempt( attempt.clone() );
+        let failed = !beam::trace_beam( &self.level ).bombs.is_empty();
+        self.level.set_attempt( AttemptState::default() );
+        self.place( state, false );
+        return failed;
+    }
+
+    // The attempt after the beams of the given layout have spent the one-shot gems they light
+    fn spend_gems( &mut self, state: &PieceState, attempt: &AttemptState ) -> AttemptState {
+        self.place( state, true );
+        self.level.set_attempt( attempt.clone() );
+        let path = beam::trace_beam( &self.level );
+        self.level.spend_gems( &path.gems );
+        let spent = self.level.attempt().clone();
+        self.level.set_attempt( AttemptState::default() );
+        self.place( state, false );
+        return spent;
+    }
+
+    // Whether the level is won and the open tiles the ray passes through with the pieces in the given places. Only
+    // tiles the ray first reaches after passing the tile at offset after are returned
+    fn ray_cells( &mut self, state: &PieceState, after: Option<usize> ) -> ( bool, Vec<usize> ) {
+        self.place( state, true );
+        let path = beam::trace_beam( &self.level );
+        let mut cells: Vec<usize> = Vec::new();
+        let mut seen: HashSet<usize> = HashSet::new();
+        let mut passed = after.is_none();
+        for step in path.steps() {
+            let offset = ( step.y*self.level.width+step.x ) as usize;
+            if Some( offset ) == after {
+                passed = true;
+            }
+            if seen.insert( offset ) && passed && self.level.is_open_tile( step.x, step.y ) {
+                cells.push( offset );
+            }
+        }
+        let won = self.level.is_won_by( &path );
+        self.place( state, false );
+        return ( won, cells );
+    }
+
+    // Every way of turning the rotatable mirrors
+    fn rotations( &self ) -> Vec<PieceState> {
+        let mut rotations: Vec<PieceState> = vec![ Vec::new() ];
+        for offset in self.rotatables.iter() {
+            let orientations = [ Tile::RotatableTopLeft, Tile::RotatableTopRight, Tile::RotatableBottomRight, Tile::RotatableBottomLeft ];
+            rotations = rotations.iter().flat_map( |rotation| orientations.iter().map( move |tile| {
+                let mut next = rotation.clone();
+                next.push( ( *offset, *tile ) );
+                next
+            } ) ).collect();
+        }
+        return rotations;
+    }
+
+    // The smallest sets of pieces on the ray that light every gem. Every solved state has one of these sets on its ray.
+    // Sets are built by adding pieces in the order the ray reaches them so each set is only checked once. Every set
+    // holds all the rotatable mirrors as they never leave the level.
+    // The search stops after max_nodes sets or max_placements solved sets. The flag is false if it stopped early
+    pub fn solved_placements( &mut self, max_nodes: usize, max_placements: usize ) -> ( Vec<PieceState>, bool ) {
+        let mut placements: Vec<PieceState> = Vec::new();
+        let mut found: HashSet<PieceState> = HashSet::new();
+        let mut open: Vec<(PieceState,Option<usize>)> = self.rotations().into_iter().map( |rotation| ( rotation, None ) ).collect();
+        let mut checked = 0;
+        while let Some( ( placement, last_piece ) ) = open.pop() {
+            checked += 1;
+            if checked > max_nodes {
+                return ( placements, false );
+            }
+            let ( solved, cells ) = self.ray_cells( &placement, last_piece );
+            if solved {
+                if found.insert( placement.clone() ) {
+                    if placements.len() == max_placements {
+                        return ( placements, false );
+                    }
+                    placements.push( placement );
+                }
+                continue;
+            }
+            let mut available = self.pieces.clone();
+            for ( _, tile ) in placement.iter().filter( |( _, tile )| tile.is_movable() ) {
+                let idx = available.iter().position( |piece| piece == tile ).unwrap();
+                available.remove( idx );
+            }
+            available.sort_by_key( |tile| *tile as u8 );
+            available.dedup();
+            for cell in cells.iter() {
+                for tile in available.iter() {
+                    let mut next = placement.clone();
+                    next.push( ( *cell, *tile ) );
+                    next.sort_by_key( |( offset, _ )| *offset );
+                    open.push( ( next, Some( *cell ) ) );
+                }
+            }
+        }
+        return ( placements, true );
+    }
+
+    // The movable pieces and rotatable mirrors the ray passes over with the pieces in the given places
+    pub fn beam_pieces( &mut self, state: &PieceState ) -> PieceState {
+        self.place( state, true );
+        let path = beam::trace_beam( &self.level );
+        let mut pieces: PieceState = Vec::new();
+        for step in path.steps() {
+            let offset = ( step.y*self.level.width+step.x ) as usize;
+            let tile = self.level.front_tile( step.x, step.y );
+            if is_piece( tile ) && !pieces.iter().any( |( piece_offset, _ )| *piece_offset == offset ) {
+                pieces.push( ( offset, tile ) );
+            }
+        }
+        self.place( state, false );
+        pieces.sort_by_key( |( offset, _ )| *offset );
+        return pieces;
+    }
+
+    // Lower bound on the drags left: each drag or turn can put at most one piece into place
+    fn moves_left( placements: &Vec<PieceState>, state: &PieceState ) -> usize {
+        let mut best = usize::MAX;
+        for placement in placements.iter() {
+            let missing = placement.iter().filter( |( offset, tile )| {
+                state.binary_search_by_key( offset, |( state_offset, _ )| *state_offset ).map_or( true, |idx| state[ idx ].1 != *tile )
+            } ).count();
+            best = best.min( missing );
+        }
+        return best;
+    }
+
+    // All states reachable with a single drag or turn together with the move that gets there
+    pub fn next_states( &mut self, state: &PieceState ) -> Vec<(PieceState,Move)> {
+        let mut next: Vec<(PieceState,Move)> = Vec::new();
+        self.place( state, true );
+        // Pieces can be dragged across the doors the beams hold open before the drag
+        let open_doors = if self.doors { beam::trace_beam( &self.level ).open_doors } else { Vec::new() };
+        for ( idx, ( start, tile ) ) in state.iter().enumerate() {
+            let ( start_x, start_y ) = self.position( *start );
+            if tile.is_rotatable() {
+                for clockwise in [ true, false ].iter() {
+                    let mut new_state = state.clone();
+                    new_state[ idx ].1 = tile.rotated( *clockwise );
+                    next.push( ( new_state, Move{ tile: tile.rotated( *clockwise ), from: ( start_x, start_y ), to: ( start_x, start_y ) } ) );
+                }
+                continue;
+            }
+            // The piece is lifted while it is dragged so its own tile is open
+            self.level.set_front_tile( start_x, start_y, Tile::EmptyPiece );
+            let mut reached: HashSet<usize> = HashSet::new();
+            let mut open: Vec<usize> = vec![ *start ];
+            reached.insert( *start );
+            while let Some( offset ) = open.pop() {
+                let ( x, y ) = self.position( offset );
+                let mut neighbours: Vec<(u32,u32)> = Vec::new();
+                if x > 0 { neighbours.push( ( x-1, y ) ); }
+                if x < self.level.width-1 { neighbours.push( ( x+1, y ) ); }
+                if y > 0 { neighbours.push( ( x, y-1 ) ); }
+                if y < self.level.height-1 { neighbours.push( ( x, y+1 ) ); }
+                for ( nx, ny ) in neighbours {
+                    let neighbour = ( ny*self.level.width+nx ) as usize;
+                    if !reached.contains( &neighbour ) && self.level.is_passable_tile( nx, ny, &open_doors ) {
+                        reached.insert( neighbour );
+                        open.push( neighbour );
+                    }
+                }
+            }
+            self.level.set_front_tile( start_x, start_y, *tile );
+
+            // Open doors can be crossed but a piece cant be left on one
+            let mut destinations: Vec<usize> = reached.into_iter().filter( |offset| {
+                let ( x, y ) = self.position( *offset );
+                offset != start && self.level.is_open_tile( x, y )
+            } ).collect();
+            destinations.sort();
+            for destination in destinations {
+                let mut new_state = state.clone();
+                new_state[ idx ].0 = destination;
+                new_state.sort_by_key( |( offset, _ )| *offset );
+                next.push( ( new_state, Move{ tile: *tile, from: ( start_x, start_y ), to: self.position( destination ) } ) );
+            }
+        }
+        self.place( state, false );
+        return next;
+    }
+
+    // A* search for the fewest moves that light every gem. When there are too many solved placements to use
+    // as a guide the search heads for the pieces of the stored solution instead and then searches breadth first
+    // for anything shorter. Found is returned if that runs out of states. Without a stored solution it is a plain
+    // breadth first search. Spent one-shot gems change which placements win so levels with them are always
+    // searched breadth first
+    pub fn solve( &mut self, start: &PieceState, max_states: usize ) -> SolveResult {
+        if self.placements.is_none() && self.one_shot {
+            self.placements = Some( vec![ Vec::new() ] );
+            self.placements_complete = true;
+        }
+        if self.placements.is_none() {
+            let ( placements, complete ) = self.solved_placements( MAX_PLACEMENT_NODES, usize::MAX );
+            self.placements = Some( if complete { placements } else { vec![ Vec::new() ] } );
+            self.placements_complete = complete;
+        }
+        if !self.placements_complete && self.stored.is_some() {
+            let target = self.beam_pieces( &self.stored.clone().unwrap() );
+            if let SolveResult::Solved( route ) = self.search( &vec![ target ], start, max_states, usize::MAX ) {
+                if route.is_empty() {
+                    return SolveResult::Solved( route );
+                }
+                match self.search( &vec![ Vec::new() ], start, max_states, route.len()-1 ) {
+                    SolveResult::Solved( moves ) => return SolveResult::Solved( moves ),
+                    SolveResult::Unsolvable => return SolveResult::Solved( route ),
+                    _ => return SolveResult::Found( route )
+                }
+            }
+        }
+        let placements = self.placements.take().unwrap();
+        let result = self.search( &placements, start, max_states, usize::MAX );
+        self.placements = Some( placements );
+        return result;
+    }
+
+    // Count the dead ends among the layouts reachable from start, exploring at most max_states of them breadth first.
+    // Solved layouts and layouts that light a bomb end the level so nothing follows them. Without doors or one-shot
+    // gems every other move can be taken back, so a layout that does not end the level can always get back to the
+    // start and is a dead end only if the level is unsolvable. solvable says which it is and None means unknown.
+    // Otherwise a layout is a dead end if every layout reachable from it was explored and none of them is solved
+    pub fn dead_ends( &mut self, start: &PieceState, solvable: Option<bool>, max_states: usize ) -> Option<DeadEnds> {
+        let reversible = !self.doors && !self.one_shot;
+        if reversible && solvable.is_none() {
+            return None;
+        }
+        if reversible && !self.bombs && solvable == Some( true ) {
+            return Some( DeadEnds{ dead_ends: 0, explored: 0 } );
+        }
+        let mut layouts: Vec<(PieceState,AttemptState)> = vec![ ( start.clone(), AttemptState::default() ) ];
+        let mut index: HashMap<(PieceState,AttemptState),usize> = HashMap::new();
+        index.insert( layouts[ 0 ].clone(), 0 );
+        let mut parents: Vec<Vec<usize>> = vec![ Vec::new() ];
+        let mut solved: Vec<usize> = Vec::new();
+        let mut failed = 0;
+        let mut explored = 0;
+        while explored < layouts.len() && layouts.len() < max_states {
+            let ( state, attempt ) = layouts[ explored ].clone();
+            let idx = explored;
+            explored += 1;
+            if self.is_solved_in( &state, &attempt ) {
+                solved.push( idx );
+                continue;
+            }
+            if self.fails_in( &state, &attempt ) {
+                failed += 1;
+                continue;
+            }
+            let next_attempt = if self.one_shot { self.spend_gems( &state, &attempt ) } else { attempt };
+            for ( next_state, _ ) in self.next_states( &state ) {
+                let key = ( next_state, next_attempt.clone() );
+                let next = match index.get( &key ) {
+                    Some( next ) => *next,
+                    None => {
+                        layouts.push( key.clone() );
+                        index.insert( key, layouts.len()-1 );
+                        parents.push( Vec::new() );
+                        layouts.len()-1
+                    }
+                };
+                if !reversible {
+                    parents[ next ].push( idx );
+                }
+            }
+        }
+        if reversible {
+            let dead_ends = if solvable == Some( true ) { failed } else { explored };
+            return Some( DeadEnds{ dead_ends, explored } );
+        }
+        // Work back from the solved layouts and from the layouts not explored, which might lead to a solution
+        let mut live: Vec<bool> = ( 0..layouts.len() ).map( |idx| idx >= explored ).collect();
+        let mut open: Vec<usize> = ( explored..layouts.len() ).collect();
+        for idx in solved {
+            live[ idx ] = true;
+            open.push( idx );
+        }
+        while let Some( idx ) = open.pop() {
+            for parent in parents[ idx ].iter() {
+                if !live[ *parent ] {
+                    live[ *parent ] = true;
+                    open.push( *parent );
+                }
+            }
+        }
+        let dead_ends = live[ ..explored ].iter().filter( |live| !**live ).count();
+        return Some( DeadEnds{ dead_ends, explored } );
+    }
+
+    // Fewest drags to a solved state that has the given pieces in place
+    pub fn solve_towards( &mut self, start: &PieceState, target: &PieceState, max_states: usize ) -> SolveResult {
+        return self.search( &vec![ target.clone() ], start, max_states, usize::MAX );
+    }
+
+    // Only solutions of up to max_moves drags are looked for
+    fn search( &mut self, placements: &Vec<PieceState>, start: &PieceState, max_states: usize, max_moves: usize ) -> SolveResult {
+        if placements.len() == 0 {
+            return SolveResult::Unsolvable;
+        }
+        // Every state visited along with the one-shot gems spent before it and the state and drag it was reached from
+        let mut states: Vec<(PieceState,AttemptState,usize,Option<Move>)> = vec![ ( start.clone(), AttemptState::default(), 0, None ) ];
+        let mut moves_to: HashMap<(PieceState,AttemptState),usize> = HashMap::new();
+        let mut queue: BinaryHeap<Reverse<(usize,usize,usize,usize)>> = BinaryHeap::new();
+        moves_to.insert( ( start.clone(), AttemptState::default() ), 0 );
+        queue.push( Reverse( ( Solver::moves_left( placements, start ), Solver::moves_left( placements, start ), 0, 0 ) ) );
+        while let Some( Reverse( ( _, left, moves, idx ) ) ) = queue.pop() {
+            let ( state, attempt ) = ( states[ idx ].0.clone(), states[ idx ].1.clone() );
+            if moves_to[ &( state.clone(), attempt.clone() ) ] < moves {
+                continue;
+            }
+            if left == 0 && self.is_solved_in( &state, &attempt ) {
+                let mut solution: Vec<Move> = Vec::new();
+                let mut current = idx;
+                while let Some( piece_move ) = states[ current ].3 {
+                    solution.push( piece_move );
+                    current = states[ current ].2;
+                }
+                solution.reverse();
+                return SolveResult::Solved( solution );
+            }
+            if moves == max_moves || self.fails_in( &state, &attempt ) {
+                continue;
+            }
+            // The one-shot gems this layout lights stay spent after the next move
+            let next_attempt = if self.one_shot { self.spend_gems( &state, &attempt ) } else { attempt };
+            for ( next_state, piece_move ) in self.next_states( &state ) {
+                let key = ( next_state, next_attempt.clone() );
+                if moves_to.get( &key ).map_or( false, |known| *known <= moves+1 ) {
+                    continue;
+                }
+                if states.len() >= max_states {
+                    return SolveResult::GaveUp( states.len() );
+                }
+                let next_left = Solver::moves_left( placements, &key.0 );
+                moves_to.insert( key.clone(), moves+1 );
+                states.push( ( key.0, key.1, idx, Some( piece_move ) ) );
+                queue.push( Reverse( ( moves+1+next_left, next_left, moves+1, states.len()-1 ) ) );
+            }
+        }
+        return SolveResult::Unsolvable;
+    }
+}
+
+pub fn solve_level( level: &Level, max_states: usize ) -> SolveResult {
+    let mut solver = Solver::new( level );
+    return solver.solve( &Solver::initial_state( level ), max_states );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::level::{Level, Tile};
+    use super::super::level_text;
+    use super::{solve_level, Move, SolveResult, Solver};
+
+    // A level with floor under every tile and beams absorbed at the edges
+    fn level( front: &[&str] ) -> Level {
+        let floor: Vec<String> = front.iter().map( |row| "2".repeat( row.len() ) ).collect();
+        let empty: Vec<String> = front.iter().map( |row| ".".repeat( row.len() ) ).collect();
+        let text = format!( "beam_puzzle level 0\nhas_solution 0\nedges absorb\nback\n{}\nfront\n{}\nsolution\n{}\n", floor.join( "\n" ), front.join( "\n" ), empty.join( "\n" ) );
+        return level_text::level_from_text( &text ).unwrap();
+    }
+
+    // The switch holds the door open so the mirror can be dragged through it to the top row
+    #[test]
+    fn pieces_cross_open_doors() {
+        let result = solve_level( &level( &[ ">S.....", "---N--G", "b......" ] ), 10_000 );
+        assert_eq!( result, SolveResult::Solved( vec![ Move{ tile: Tile::MovableTopRight, from: ( 0, 2 ), to: ( 6, 0 ) } ] ) );
+    }
+
+    #[test]
+    fn pieces_do_not_cross_walls() {
+        assert_eq!( solve_level( &level( &[ ">S.....", "------G", "b......" ] ), 10_000 ), SolveResult::Unsolvable );
+    }
+
+    // The mirror can only go where the blocker is. Moving the blocker straight off the beam lets the beam reach the
+    // bomb so it has to be parked in front of the bomb until the mirror is in place
+    #[test]
+    fn layouts_that_light_a_bomb_end_the_level() {
+        let moves = |front: &[&str]| match solve_level( &level( front ), 10_000 ) {
+            SolveResult::Solved( moves ) => moves.len(),
+            result => panic!( "{:?}", result )
+        };
+        assert_eq!( moves( &[ ">..:O", "...G.", "b...." ] ), 3 );
+        assert_eq!( moves( &[ ">..:.", "...G.", "b...." ] ), 2 );
+    }
+
+    // The switch is off the beam so the door stays shut
+    #[test]
+    fn pieces_do_not_cross_closed_doors() {
+        assert_eq!( solve_level( &level( &[ ">......", "---N--G", "b.S...." ] ), 10_000 ), SolveResult::Unsolvable );
+    }
+
+    // The start lights and spends the one-shot gem so the mirror only has to send the beam to the other gem
+    #[test]
+    fn spent_one_shot_gems_stay_lit() {
+        let result = solve_level( &level( &[ ">.y", "...", "bG." ] ), 10_000 );
+        assert_eq!( result, SolveResult::Solved( vec![ Move{ tile: Tile::MovableTopRight, from: ( 0, 2 ), to: ( 1, 0 ) } ] ) );
+        assert_eq!( solve_level( &level( &[ ">.G", "...", "bG." ] ), 10_000 ), SolveResult::Unsolvable );
+    }
+
+    // Dead ends, explored layouts for the level
+    fn dead_ends( front: &[&str] ) -> ( usize, usize ) {
+        let level = level( front );
+        let mut solver = Solver::new( &level );
+        let start = Solver::initial_state( &level );
+        let solvable = match solver.solve( &start, 10_000 ) {
+            SolveResult::Solved( _ ) => Some( true ),
+            SolveResult::Unsolvable => Some( false ),
+            _ => None
+        };
+        let dead_ends = solver.dead_ends( &start, solvable, 10_000 ).unwrap();
+        return ( dead_ends.dead_ends, dead_ends.explored );
+    }
+
+    // Every move can be taken back so only the layouts that light the bomb are dead ends
+    #[test]
+    fn dead_ends_are_layouts_that_light_a_bomb() {
+        let ( dead, explored ) = dead_ends( &[ ">..:O", "...G.", "b...." ] );
+        assert!( dead > 0 && dead < explored, "{} of {}", dead, explored );
+        assert_eq!( dead_ends( &[ ">..:.", "...G.", "b...." ] ).0, 0 );
+    }
+
+    #[test]
+    fn every_layout_of_an_unsolvable_level_is_a_dead_end() {
+        let ( dead, explored ) = dead_ends( &[ ">......", "---N--G", "b.S...." ] );
+        assert!( explored > 0 );
+        assert_eq!( dead, explored );
+    }
+}
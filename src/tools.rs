@@ -0,0 +1,270 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use super::level_text;
+use super::level_pack::{self, LevelPack};
+use super::level::SolutionStatus;
+use super::assets::Assets;
+use super::solver::{self, SolveResult};
+use super::generator::{self, GeneratorSettings};
+use super::difficulty;
+
+// Command line tools that run without opening a window.
+// Returns the process exit code if the arguments named a tool
+pub fn run_tool( args: &[String] ) -> Option<i32> {
+    if args.len() == 0 {
+        return None;
+    }
+    let result = match ( args[ 0 ].as_str(), args.get( 1 ) ) {
+        ( "to-text", Some( dir ) ) => convert_levels( Path::new( dir ), "mp", "txt" ),
+        ( "to-binary", Some( dir ) ) => convert_levels( Path::new( dir ), "txt", "mp" ),
+        ( "to-text", None ) | ( "to-binary", None ) => Err( format!( "usage: {} <level dir>", args[ 0 ] ) ),
+        ( "pack", Some( dir ) ) if args.len() >= 3 => write_pack( Path::new( dir ), Path::new( &args[ 2 ] ), args.get( 3 ).map_or( "Beam Puzzle", |name| name.as_str() ) ),
+        ( "pack", _ ) => Err( "usage: pack <level dir> <pack file> [pack name]".to_string() ),
+        ( "verify-levels", dir ) => load_pack( dir ).and_then( |pack| verify_levels( &pack ) ),
+        ( "solve-levels", dir ) => load_pack( dir ).and_then( |pack| solve_levels( &pack ) ),
+        ( "difficulty-report", dir ) => load_pack( dir ).and_then( |pack| difficulty_report( &pack ) ),
+        ( "check-solutions", dir ) => load_pack( dir ).and_then( |pack| check_solutions( &pack ) ),
+        ( "generate", Some( dir ) ) if args.len() >= 3 => generate_levels( Path::new( dir ), &args[ 2 ], args.get( 3 ).map_or( "1", |count| count.as_str() ) ),
+        ( "generate", _ ) => Err( "usage: generate <level dir> <seed> [count]".to_string() ),
+        _ => return None
+    };
+    match result {
+        Ok( () ) => return Some( 0 ),
+        Err( message ) => {
+            println!( "{}", message );
+            return Some( 1 );
+        }
+    }
+}
+
+fn files_with_extension( dir: &Path, extension: &str ) -> Result<Vec<PathBuf>,String> {
+    let entries = fs::read_dir( dir ).map_err( |err| format!( "Failed to read {:?}: {}", dir, err ) )?;
+    let mut files: Vec<PathBuf> = entries.filter_map( |entry| entry.ok() ).map( |entry| entry.path() )
+        .filter( |path| path.extension().map_or( false, |ext| ext == extension ) ).collect();
+    files.sort();
+    return Ok( files );
+}
+
+// Convert every level in dir between the binary and text formats. Each conversion is checked to be lossless
+fn convert_levels( dir: &Path, from: &str, to: &str ) -> Result<(),String> {
+    let mut failures = 0;
+    for path in files_with_extension( dir, from )? {
+        let input = fs::read( &path ).map_err( |err| format!( "Failed to read {:?}: {}", path, err ) )?;
+        let output = if to == "txt" {
+            level_text::mp_to_text( &input ).map( |text| text.into_bytes() )
+        } else {
+            level_text::text_to_mp( &String::from_utf8_lossy( &input ) )
+        };
+        match output {
+            Ok( output ) => {
+                let out_path = path.with_extension( to );
+                fs::write( &out_path, output ).map_err( |err| format!( "Failed to write {:?}: {}", out_path, err ) )?;
+                println!( "{:?} -> {:?}", path, out_path );
+            },
+            Err( err ) => {
+                println!( "{:?}: {}", path, err );
+                failures += 1;
+            }
+        }
+    }
+    if failures > 0 {
+        return Err( format!( "{} levels failed to convert", failures ) );
+    }
+    return Ok( () );
+}
+
+// Bundle the numbered levels of a directory into a single pack file
+fn write_pack( dir: &Path, pack_path: &Path, name: &str ) -> Result<(),String> {
+    let pack = LevelPack::from_directory( dir, name ).map_err( |err| format!( "Failed to read levels from {:?}: {}", dir, err ) )?;
+    for number in 1..=pack.len() {
+        pack.level( number ).map_err( |err| format!( "Level {} is invalid: {}", number, err ) )?;
+    }
+    fs::write( pack_path, pack.to_bytes() ).map_err( |err| format!( "Failed to write {:?}: {}", pack_path, err ) )?;
+    println!( "Wrote {} levels to {:?}", pack.len(), pack_path );
+    return Ok( () );
+}
+
+// Levels from the given directory or the campaign the game would play
+fn load_pack( dir: Option<&String> ) -> Result<LevelPack,String> {
+    let pack = match dir {
+        Some( dir ) => LevelPack::from_directory( Path::new( dir ), "Beam Puzzle" ),
+        None => level_pack::load_default_pack( &Assets::new( None ) )
+    };
+    return pack.map_err( |err| format!( "Failed to load levels: {}", err ) );
+}
+
+// Check that the solution layer of every level solves it. Fails if any level has a broken or missing solution
+fn verify_levels( pack: &LevelPack ) -> Result<(),String> {
+    let mut failures = 0;
+    for number in 1..=pack.len() {
+        let status = match pack.level( number ) {
+            Ok( level ) => level.verify_solution(),
+            Err( err ) => {
+                println!( "level {}: failed to load: {}", number, err );
+                failures += 1;
+                continue;
+            }
+        };
+        match status {
+            SolutionStatus::Solved => println!( "level {}: ok", number ),
+            SolutionStatus::Missing => println!( "level {}: no solution stored", number ),
+            SolutionStatus::PiecesDiffer => println!( "level {}: solution pieces differ from the level pieces", number ),
+            SolutionStatus::Blocked( x, y ) => println!( "level {}: solution piece at ( {},{} ) is on an occupied tile", number, x, y ),
+            SolutionStatus::GemsMissed{ hit, total } => println!( "level {}: solution hits {} of {} gems", number, hit, total ),
+            SolutionStatus::ReceiversMissed{ hit, total } => println!( "level {}: solution reaches {} of {} receivers", number, hit, total ),
+            SolutionStatus::BombLit( x, y ) => println!( "level {}: solution lights the bomb at ( {},{} )", number, x, y ),
+            SolutionStatus::GemOrderBroken => println!( "level {}: solution lights the gems out of order", number ),
+        }
+        if status != SolutionStatus::Solved {
+            failures += 1;
+        }
+    }
+    if failures > 0 {
+        return Err( format!( "{} of {} levels have broken or missing solutions", failures, pack.len() ) );
+    }
+    return Ok( () );
+}
+
+const SOLVER_MAX_STATES: usize = 2_000_000;
+
+// Find the fewest drags needed for each level. Levels too big to search exhaustively are solved by following
+// their stored solution and reported with that many drags as an upper bound
+fn solve_levels( pack: &LevelPack ) -> Result<(),String> {
+    let mut failures = 0;
+    for number in 1..=pack.len() {
+        let level = match pack.level( number ) {
+            Ok( level ) => level,
+            Err( err ) => {
+                println!( "level {}: failed to load: {}", number, err );
+                failures += 1;
+                continue;
+            }
+        };
+        let ( moves, proven ) = match solver::solve_level( &level, SOLVER_MAX_STATES ) {
+            SolveResult::Solved( moves ) => ( moves, true ),
+            SolveResult::Found( moves ) => ( moves, false ),
+            SolveResult::Unsolvable => {
+                println!( "level {}: unsolvable", number );
+                failures += 1;
+                continue;
+            },
+            SolveResult::GaveUp( states ) => {
+                println!( "level {}: gave up after {} states", number, states );
+                failures += 1;
+                continue;
+            }
+        };
+        if proven {
+            println!( "level {}: {} moves", number, moves.len() );
+        } else {
+            println!( "level {}: at most {} moves, the search ran out of states looking for fewer", number, moves.len() );
+        }
+        for piece_move in moves.iter() {
+            if piece_move.from == piece_move.to {
+                println!( "    turn ( {},{} ) to {:?}", piece_move.from.0, piece_move.from.1, piece_move.tile );
+            } else {
+                println!( "    {:?} ( {},{} ) -> ( {},{} )", piece_move.tile, piece_move.from.0, piece_move.from.1, piece_move.to.0, piece_move.to.1 );
+            }
+        }
+    }
+    if failures > 0 {
+        return Err( format!( "{} of {} levels could not be solved", failures, pack.len() ) );
+    }
+    return Ok( () );
+}
+
+const DIFFICULTY_MAX_STATES: usize = 200_000;
+
+fn optional_count( count: Option<usize> ) -> String {
+    return count.map_or( "?".to_string(), |count| count.to_string() );
+}
+
+// Score every level and list them from easiest to hardest
+fn difficulty_report( pack: &LevelPack ) -> Result<(),String> {
+    let mut scores: Vec<(u32,f32)> = Vec::new();
+    println!( "level  pieces  moves  teleports  layouts  dead ends  score" );
+    for number in 1..=pack.len() {
+        let level = pack.level( number ).map_err( |err| format!( "level {}: failed to load: {}", number, err ) )?;
+        let difficulty = difficulty::analyze_level( &level, DIFFICULTY_MAX_STATES );
+        println!( "{:>5}  {:>6}  {:>5}  {:>9}  {:>7.1e}  {:>9}  {:>5.1}", number, difficulty.movable_pieces, optional_count( difficulty.min_moves ),
+            difficulty.teleports_used, difficulty.search_space, optional_count( difficulty.dead_ends ), difficulty.score );
+        scores.push( ( number, difficulty.score ) );
+    }
+    scores.sort_by( |a, b| a.1.partial_cmp( &b.1 ).unwrap() );
+    let order: Vec<String> = scores.iter().map( |( number, _ )| number.to_string() ).collect();
+    println!( "easiest to hardest: {}", order.join( " " ) );
+    return Ok( () );
+}
+
+const MAX_PIECE_SETS: usize = 1000;
+
+// List the levels that can be solved in more ways than the stored solution or with fewer drags than intended.
+// A way is a smallest set of pieces on the ray that wins, wherever the other pieces are
+fn check_solutions( pack: &LevelPack ) -> Result<(),String> {
+    let mut flagged = 0;
+    for number in 1..=pack.len() {
+        let level = pack.level( number ).map_err( |err| format!( "level {}: failed to load: {}", number, err ) )?;
+        let check = difficulty::check_solutions( &level, MAX_PIECE_SETS, SOLVER_MAX_STATES );
+        let count = if check.complete { check.piece_sets.len().to_string() } else { format!( "at least {}", check.piece_sets.len() ) };
+        let mut notes: Vec<String> = Vec::new();
+        if check.stored.is_none() {
+            notes.push( "no working stored solution".to_string() );
+        } else if check.is_unique() {
+            notes.push( "stored solution is the only one".to_string() );
+        } else if check.complete && !check.piece_sets.contains( check.stored.as_ref().unwrap() ) {
+            notes.push( "stored solution uses more pieces than needed".to_string() );
+        }
+        if check.has_shortcut() {
+            notes.push( format!( "shortcut: {} moves instead of {}", check.shortest_moves.unwrap(), check.intended_moves.unwrap() ) );
+            flagged += 1;
+        }
+        notes.insert( 0, format!( "{} winning piece sets", count ) );
+        println!( "level {}: {}", number, notes.join( ", " ) );
+    }
+    println!( "{} levels can be solved in fewer moves than intended", flagged );
+    return Ok( () );
+}
+
+// Write count generated levels to level1.mp, level2.mp, .. using consecutive seeds
+fn generate_levels( dir: &Path, seed: &str, count: &str ) -> Result<(),String> {
+    let seed: u64 = seed.parse().map_err( |_| format!( "seed must be a number: {}", seed ) )?;
+    let count: u64 = count.parse().map_err( |_| format!( "count must be a number: {}", count ) )?;
+    if count > 0 && seed.checked_add( count - 1 ).is_none() {
+        return Err( format!( "seed {} and count {} go past the largest seed {}", seed, count, u64::MAX ) );
+    }
+    fs::create_dir_all( dir ).map_err( |err| format!( "Failed to create {:?}: {}", dir, err ) )?;
+    let settings = GeneratorSettings::default();
+    for number in 1..=count {
+        let level_seed = seed + number - 1;
+        let level = generator::generate_level( level_seed, &settings ).ok_or_else( || format!( "No level found for seed {}", level_seed ) )?;
+        let path = dir.join( format!( "level{}.mp", number ) );
+        level.save( &path ).map_err( |err| format!( "Failed to write {:?}: {}", path, err ) )?;
+        println!( "seed {} -> {:?}", level_seed, path );
+    }
+    return Ok( () );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+    use super::files_with_extension;
+    use super::super::level::{Level, SolutionStatus};
+
+    // level0 is the menu background and has no solution to check
+    const BACKGROUND_LEVEL: &str = "level0.mp";
+
+    #[test]
+    fn shipped_solutions_win() {
+        let dir = Path::new( env!( "CARGO_MANIFEST_DIR" ) ).join( "levels" );
+        let files = files_with_extension( &dir, "mp" ).unwrap();
+        assert!( files.len() > 0 );
+        for path in files {
+            let level = Level::from_bytes( &fs::read( &path ).unwrap() ).unwrap_or_else( |err| panic!( "{:?}: {}", path, err ) );
+            if path.file_name().unwrap() == BACKGROUND_LEVEL {
+                continue;
+            }
+            assert_eq!( level.verify_solution(), SolutionStatus::Solved, "{:?}", path );
+        }
+    }
+}